@@ -0,0 +1,102 @@
+//! Measures how long an event takes from the moment a fake emitter
+//! writes its line to the moment the consumer receives it.
+//!
+//! The emitter writes to a socket with pauses longer and shorter than the
+//! read timeout of the old poll-based loop, so that lines arrive while the
+//! reader is blocked, right after a read timed out and in bursts. The same
+//! lines are measured against a baseline of that loop: a read with a
+//! 314ms timeout followed by a 100ms wait for actions.
+//!
+//! Run with `cargo bench --bench latency`.
+use mac_disk_monitor::{Action, Event, Monitor, SocketSource};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LINE: &str = "***DiskPeek ('disk4s2') Time=20220108-20:22:35.8607";
+const PAUSES_MS: &[u64] = &[0, 1, 5, 50, 120, 310, 320, 400];
+const ROUNDS: usize = 4;
+
+/// The loop `stream_events` ran before the reader blocked on its own
+fn poll_based(stream: TcpStream, action: Receiver<Action>) -> Receiver<Event> {
+    let (sender, events) = channel();
+    thread::spawn(move || {
+        stream
+            .set_read_timeout(Some(Duration::from_millis(314)))
+            .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+        loop {
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => return,
+                Ok(_) => {
+                    let event = Event::parse(&String::from_utf8_lossy(&line)).unwrap();
+                    line.clear();
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+                Err(_) => {}
+            }
+            if let Ok(Action::Stop) = action.recv_timeout(Duration::from_millis(100)) {
+                return;
+            }
+        }
+    });
+    events
+}
+
+/// Sends the line after each pause, returning how long each took to arrive
+fn measure(mut emitter: TcpStream, mut receive: impl FnMut()) -> Vec<Duration> {
+    let mut latencies = Vec::new();
+    for _ in 0..ROUNDS {
+        for pause in PAUSES_MS {
+            thread::sleep(Duration::from_millis(*pause));
+            let sent = Instant::now();
+            writeln!(emitter, "{}", LINE).unwrap();
+            receive();
+            latencies.push(sent.elapsed());
+        }
+    }
+    latencies.sort();
+    latencies
+}
+
+/// A connected emitter and the other end of its socket
+fn connected() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (emitter, _) = listener.accept().unwrap();
+    (emitter, stream)
+}
+
+fn report(name: &str, latencies: &[Duration]) {
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    println!("{}:", name);
+    println!("  events:  {}", latencies.len());
+    println!("  median:  {:?}", percentile(50));
+    println!("  p90:     {:?}", percentile(90));
+    println!("  max:     {:?}", percentile(100));
+}
+
+#[cfg(not(tarpaulin_include))]
+fn main() {
+    let (emitter, stream) = connected();
+    let (action, receiver) = channel();
+    let events = poll_based(stream, receiver);
+    let baseline = measure(emitter, || {
+        events.recv().unwrap();
+    });
+    let _ = action.send(Action::Stop);
+
+    let (emitter, stream) = connected();
+    let mut monitor = Monitor::from_source(SocketSource::from_tcp(stream).unwrap());
+    let blocking = measure(emitter, || {
+        monitor.next().unwrap().unwrap();
+    });
+
+    report("poll-based baseline", &baseline);
+    report("blocking reader", &blocking);
+}
@@ -11,11 +11,11 @@ fn main() {
         // poll for events every 1.5 seconds
         match receiver.recv_timeout(Duration::from_millis(1500)) {
             Ok(event) => match event {
-                Some(event) => {
+                Ok(event) => {
                     println!("{}", event.to_json());
                 }
-                None => {
-                    eprintln!("thread has stopped...");
+                Err(e) => {
+                    eprintln!("Error: {}", e);
                 }
             },
             Err(e) => {
@@ -26,5 +26,7 @@ fn main() {
     }
     action.send(Action::Stop).unwrap();
     eprintln!("waiting for thread to stop...");
-    thread.join().unwrap().unwrap()
+    if let Err(e) = thread.join().unwrap() {
+        eprintln!("thread stopped: {}", e);
+    }
 }
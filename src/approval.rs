@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The keys `diskutil activity` prints inside the parentheses of a
+/// dissenting comment, e.g. `Dissenting (status = 0x0000c010, ...)`
+const DISSENT_KEYS: &[&str] = &["status", "string", "message", "process", "pid"];
+
+/// How a `Disk*Approval` callback was answered, parsed from its `Comment=`.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::ApprovalOutcome;
+///
+/// assert_eq!(ApprovalOutcome::parse("Approving"), ApprovalOutcome::Approving);
+///
+/// let outcome = ApprovalOutcome::parse("Dissenting (status = 0x0000c010, string = 'Volume in use', process = 4242 Finder)");
+/// let dissent = outcome.dissent().unwrap();
+/// assert_eq!(dissent.status, Some(0xc010));
+/// assert_eq!(dissent.message.as_deref(), Some("Volume in use"));
+/// assert_eq!(dissent.process.as_deref(), Some("4242 Finder"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalOutcome {
+    /// Every approval callback let the operation through
+    Approving,
+    /// Some process refused the mount, unmount or eject
+    Dissenting(Dissent),
+    /// A comment this crate does not understand, kept verbatim
+    Other(String),
+}
+
+/// The details of a dissenting approval, as far as `diskutil` printed them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Dissent {
+    /// The DiskArbitration status code, e.g. `0x0000c010`
+    pub status: Option<u32>,
+    /// The reason given by the dissenter
+    pub message: Option<String>,
+    /// The process that dissented, as printed by `diskutil`
+    pub process: Option<String>,
+}
+
+impl ApprovalOutcome {
+    /// Parses the text that follows `Comment=`.
+    ///
+    /// Never fails: comments that do not start with `Approving` or
+    /// `Dissenting` become [`ApprovalOutcome::Other`].
+    pub fn parse(comment: &str) -> ApprovalOutcome {
+        let comment = comment.trim();
+        if comment == "Approving" {
+            return ApprovalOutcome::Approving;
+        }
+        match comment.strip_prefix("Dissenting") {
+            Some(details) => ApprovalOutcome::Dissenting(Dissent::parse(details)),
+            None => ApprovalOutcome::Other(String::from(comment)),
+        }
+    }
+    /// Whether the operation was allowed
+    pub fn is_approving(&self) -> bool {
+        matches!(self, ApprovalOutcome::Approving)
+    }
+    /// Whether some process blocked the operation
+    pub fn is_dissenting(&self) -> bool {
+        matches!(self, ApprovalOutcome::Dissenting(_))
+    }
+    /// The dissent details, if the operation was blocked
+    pub fn dissent(&self) -> Option<&Dissent> {
+        match self {
+            ApprovalOutcome::Dissenting(dissent) => Some(dissent),
+            _ => None,
+        }
+    }
+}
+
+impl Dissent {
+    /// Parses what follows `Dissenting`, e.g. `(status = 0x0000c010, string = 'busy')`
+    fn parse(details: &str) -> Dissent {
+        let details = details.trim();
+        let details = details
+            .strip_prefix('(')
+            .and_then(|details| details.strip_suffix(')'))
+            .unwrap_or(details);
+        let mut dissent = Dissent::default();
+        for (key, value) in dissent_fields(details) {
+            match key {
+                "status" => dissent.status = parse_status(value),
+                "string" | "message" => dissent.message = Some(String::from(value)),
+                "process" | "pid" => dissent.process = Some(String::from(value)),
+                _ => {}
+            }
+        }
+        dissent
+    }
+}
+
+impl fmt::Display for ApprovalOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApprovalOutcome::Approving => f.write_str("Approving"),
+            ApprovalOutcome::Other(comment) => f.write_str(comment),
+            ApprovalOutcome::Dissenting(dissent) => {
+                f.write_str("Dissenting")?;
+                let mut fields = Vec::new();
+                if let Some(status) = dissent.status {
+                    fields.push(format!("status = {:#010x}", status));
+                }
+                if let Some(message) = &dissent.message {
+                    fields.push(format!("string = '{}'", message));
+                }
+                if let Some(process) = &dissent.process {
+                    fields.push(format!("process = {}", process));
+                }
+                if !fields.is_empty() {
+                    write!(f, " ({})", fields.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Splits `key = value, key = value` on the known keys only, so that
+/// messages may contain commas and equal signs.
+fn dissent_fields(details: &str) -> Vec<(&str, &str)> {
+    let mut starts = Vec::new();
+    for key in DISSENT_KEYS {
+        let marker = format!("{} =", key);
+        let mut from = 0;
+        while let Some(found) = details[from..].find(&marker) {
+            let start = from + found;
+            if start == 0 || details[..start].ends_with(", ") {
+                starts.push((start, *key, start + marker.len()));
+            }
+            from = start + marker.len();
+        }
+    }
+    starts.sort_unstable();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, (_, key, value_start))| {
+            let value_end = match starts.get(index + 1) {
+                Some((next, _, _)) => next - ", ".len(),
+                None => details.len(),
+            };
+            let value = details[*value_start..value_end.max(*value_start)].trim();
+            (*key, unquote(value))
+        })
+        .collect()
+}
+
+fn unquote(value: &str) -> &str {
+    ['\'', '"']
+        .iter()
+        .find_map(|quote| {
+            value
+                .strip_prefix(*quote)
+                .and_then(|value| value.strip_suffix(*quote))
+        })
+        .unwrap_or(value)
+}
+
+fn parse_status(value: &str) -> Option<u32> {
+    let value = value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .next()
+        .unwrap_or_default();
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApprovalOutcome, Dissent};
+    use k9::assert_equal;
+
+    #[test]
+    fn test_parse_approving() {
+        assert_equal!(
+            ApprovalOutcome::parse("Approving"),
+            ApprovalOutcome::Approving
+        );
+        assert_equal!(ApprovalOutcome::parse("Approving").is_approving(), true);
+    }
+    #[test]
+    fn test_parse_dissenting() {
+        assert_equal!(
+            ApprovalOutcome::parse("Dissenting"),
+            ApprovalOutcome::Dissenting(Dissent::default())
+        );
+        assert_equal!(
+            ApprovalOutcome::parse("Dissenting (status = 0x0000c010, Time=later)"),
+            ApprovalOutcome::Dissenting(Dissent {
+                status: Some(0xc010),
+                message: None,
+                process: None,
+            })
+        );
+        assert_equal!(
+            ApprovalOutcome::parse("Dissenting (status = 0x0000c010)"),
+            ApprovalOutcome::Dissenting(Dissent {
+                status: Some(0xc010),
+                message: None,
+                process: None,
+            })
+        );
+        assert_equal!(
+            ApprovalOutcome::parse(
+                "Dissenting (status = 49168, string = 'in use, try again = later', pid = 314)"
+            ),
+            ApprovalOutcome::Dissenting(Dissent {
+                status: Some(49168),
+                message: Some(String::from("in use, try again = later")),
+                process: Some(String::from("314")),
+            })
+        );
+    }
+    #[test]
+    fn test_parse_other_comments() {
+        assert_equal!(
+            ApprovalOutcome::parse("Pending"),
+            ApprovalOutcome::Other(String::from("Pending"))
+        );
+        assert_equal!(ApprovalOutcome::parse("Pending").dissent(), None);
+    }
+    #[test]
+    fn test_display_round_trip() {
+        for comment in [
+            "Approving",
+            "Dissenting",
+            "Dissenting (status = 0x0000c010, string = 'busy', process = 4242 Finder)",
+        ] {
+            assert_equal!(ApprovalOutcome::parse(comment).to_string(), comment);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// The placeholder `diskutil activity` prints for missing values
+pub const NULL_VALUE: &str = "<null>";
+
+/// The URL where the volume is mounted, e.g. `file:///Volumes/EFI/`
+pub const DA_VOLUME_PATH: &str = "DAVolumePath";
+/// The filesystem type of the volume, e.g. `apfs`
+pub const DA_VOLUME_KIND: &str = "DAVolumeKind";
+/// The name of the volume
+pub const DA_VOLUME_NAME: &str = "DAVolumeName";
+/// The UUID of the volume
+pub const DA_VOLUME_UUID: &str = "DAVolumeUUID";
+/// Whether the volume is mountable
+pub const DA_VOLUME_MOUNTABLE: &str = "DAVolumeMountable";
+/// Whether the volume is a network volume
+pub const DA_VOLUME_NETWORK: &str = "DAVolumeNetwork";
+/// The name of the media, e.g. `APPLE SSD AP0512Q Media`
+pub const DA_MEDIA_NAME: &str = "DAMediaName";
+/// The size of the media in bytes
+pub const DA_MEDIA_SIZE: &str = "DAMediaSize";
+/// Whether the media is removable
+pub const DA_MEDIA_REMOVABLE: &str = "DAMediaRemovable";
+/// Whether the media is ejectable
+pub const DA_MEDIA_EJECTABLE: &str = "DAMediaEjectable";
+/// Whether the media is writable
+pub const DA_MEDIA_WRITABLE: &str = "DAMediaWritable";
+/// The IOKit path of the media
+pub const DA_MEDIA_PATH: &str = "DAMediaPath";
+/// The BSD name of the media, e.g. `disk3s1`
+pub const DA_MEDIA_BSD_NAME: &str = "DAMediaBSDName";
+/// The protocol of the device, e.g. `USB` or `PCI-Express`
+pub const DA_DEVICE_PROTOCOL: &str = "DADeviceProtocol";
+/// Whether the device is internal
+pub const DA_DEVICE_INTERNAL: &str = "DADeviceInternal";
+/// The model of the device
+pub const DA_DEVICE_MODEL: &str = "DADeviceModel";
+/// The vendor of the device
+pub const DA_DEVICE_VENDOR: &str = "DADeviceVendor";
+
+/// The `Key = 'value'` pairs of a `DADiskDescription`, in the order
+/// they were printed.
+///
+/// Values printed as `<null>` are kept as absent so that the key is
+/// still known to have been present.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::Attributes;
+///
+/// let mut attributes = Attributes::new();
+/// attributes.insert("DAVolumeKind", "msdos");
+/// attributes.insert("DAVolumePath", "<null>");
+///
+/// assert_eq!(attributes.get("DAVolumeKind"), Some("msdos"));
+/// assert_eq!(attributes.get("DAVolumePath"), None);
+/// assert_eq!(attributes.contains_key("DAVolumePath"), true);
+/// assert_eq!(attributes.keys().collect::<Vec<_>>(), vec!["DAVolumeKind", "DAVolumePath"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Attributes {
+    entries: Vec<(String, Option<String>)>,
+}
+
+impl Attributes {
+    /// Creates an empty attribute map
+    pub fn new() -> Attributes {
+        Attributes::default()
+    }
+    /// Sets the value of the given key, keeping its original position
+    /// if it was already present. `<null>` is stored as absent.
+    pub fn insert(&mut self, key: &str, value: &str) {
+        let value = if value == NULL_VALUE {
+            None
+        } else {
+            Some(String::from(value))
+        };
+        self.insert_value(key, value);
+    }
+    /// Sets the value of the given key, where `None` means `<null>`
+    pub fn insert_value(&mut self, key: &str, value: Option<String>) {
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((String::from(key), value)),
+        }
+    }
+    /// Removes the given key, returning whether it was present
+    pub fn remove(&mut self, key: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|(k, _)| k != key);
+        len != self.entries.len()
+    }
+    /// The value of the given key, if present and not `<null>`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, value)| value.as_deref())
+    }
+    /// Whether the key was present, even if its value was `<null>`
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.entries.iter().any(|(k, _)| k == key)
+    }
+    /// The keys in the order they were inserted
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(key, _)| key.as_str())
+    }
+    /// The key/value pairs in the order they were inserted
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_deref()))
+    }
+    /// The amount of keys
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// Whether there are no keys
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// The value of the given key parsed as an unsigned integer
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.get(key).and_then(|value| value.trim().parse().ok())
+    }
+    /// The value of the given key parsed as a boolean
+    ///
+    /// Accepts the spellings used by CoreFoundation: `true`/`false`,
+    /// `yes`/`no` and `1`/`0`.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)?.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "1" => Some(true),
+            "false" | "no" | "0" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for Attributes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (key, value) in &self.entries {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Attributes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Attributes, D::Error> {
+        struct AttributesVisitor;
+
+        impl<'de> Visitor<'de> for AttributesVisitor {
+            type Value = Attributes;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a map of DADiskDescription keys to string values")
+            }
+            fn visit_map<M: MapAccess<'de>>(self, mut access: M) -> Result<Attributes, M::Error> {
+                let mut attributes = Attributes::new();
+                while let Some((key, value)) = access.next_entry::<String, Option<String>>()? {
+                    attributes.insert_value(&key, value);
+                }
+                Ok(attributes)
+            }
+        }
+
+        deserializer.deserialize_map(AttributesVisitor)
+    }
+}
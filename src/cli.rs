@@ -1,10 +1,8 @@
 extern crate clap;
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
 
-use ctrlc;
 use mac_disk_monitor::std::*;
-use mac_disk_monitor::version;
-use std::sync::mpsc::channel;
+use mac_disk_monitor::{parse_apple_time, version, MonitorBuilder, ParseOptions, ReplaySource};
 use std::time::Duration;
 
 /// Command-line entrypoint to monitor the disk events and print them out to stdout as json.
@@ -20,7 +18,7 @@ fn main() {
                 .help("the output format: (json|yaml)")
                 .short("f")
                 .default_value("json")
-                .required(true)
+                .global(true)
                 .takes_value(true),
         )
         .arg(
@@ -29,8 +27,48 @@ fn main() {
                 .help("how many milliseconds to wait for each event")
                 .short("t")
                 .default_value("3145")
-                .required(true)
+                .global(true)
                 .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("raw")
+                .long("raw")
+                .help("include the original diskutil line in each event")
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about("plays back a recorded `diskutil activity` log with its original timing")
+                .arg(
+                    Arg::with_name("log")
+                        .help("the recorded log file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("speed")
+                        .long("speed")
+                        .short("s")
+                        .help("how many times faster than recorded to play back")
+                        .default_value("1")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("fast")
+                        .long("fast")
+                        .help("play back every line without waiting"),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .help("skip the lines recorded before this time, e.g. 20220108-20:22:05")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .help("stop after the lines recorded up to this time")
+                        .takes_value(true),
+                ),
         );
     let matches = app.get_matches();
 
@@ -52,44 +90,94 @@ fn main() {
         }
     }
 
-    let (action, receiver) = channel();
-    let (thread, receiver) = stream_events(receiver);
+    let options = ParseOptions {
+        keep_raw: matches.is_present("raw"),
+        ..ParseOptions::default()
+    };
+
+    let builder = MonitorBuilder::new().parse_options(options);
+    let stream = match matches.subcommand_matches("replay") {
+        Some(replay) => replay_source(replay).map(|source| builder.build_from_source(source)),
+        None => builder.build().map_err(|e| e.to_string()),
+    };
+    let mut stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let actions = stream.actions();
     ctrlc::set_handler(move || {
-        action
+        actions
             .send(Action::Stop)
             .expect("Could not send 'stop' action to thread.");
     })
     .expect("Error setting Ctrl-C handler");
 
+    let mut failed = false;
     loop {
-        match receiver.recv_timeout(Duration::from_millis(interval)) {
-            Ok(event) => match event {
-                Some(event) => {
-                    println!(
-                        "{}",
-                        match format {
-                            "yaml" => event.to_yaml(),
-                            _ => event.to_json(),
-                        }
-                    );
-                }
-                None => {
-                    break;
-                }
-            },
-            Err(e) => match e.to_string().as_str() {
-                "timed out waiting on channel" => {}
-                "channel is empty and sending half is closed" => {
-                    break;
-                }
-                error => {
-                    eprintln!("Error: {}", error);
+        match stream.next_timeout(Duration::from_millis(interval)) {
+            Some(Ok(event)) => {
+                println!(
+                    "{}",
+                    match format {
+                        "yaml" => event.to_yaml(),
+                        _ => event.to_json(),
+                    }
+                );
+            }
+            Some(Err(e)) => {
+                eprintln!("Error: {}", e);
+                if is_fatal(&e) {
+                    failed = true;
                     break;
                 }
-            },
+            }
+            None if stream.is_finished() => break,
+            None => {}
         }
     }
     eprintln!("waiting for thread to stop...");
-    thread.join().unwrap().unwrap();
+    if let Err(e) = stream.stop() {
+        // a fatal error was already printed when it was received
+        if !failed {
+            eprintln!("Error: {}", e);
+        }
+        failed = true;
+    }
     eprintln!("done");
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Whether the error ends the stream, other errors only affect one line
+fn is_fatal(error: &Error) -> bool {
+    !matches!(error, Error::Parse { .. } | Error::Utf8(_))
+}
+
+/// The source of the `replay` subcommand
+#[cfg(not(tarpaulin_include))]
+fn replay_source(
+    matches: &ArgMatches,
+) -> Result<ReplaySource<std::io::BufReader<std::fs::File>>, String> {
+    let log = matches.value_of("log").unwrap_or_default();
+    let mut source = ReplaySource::open(log).map_err(|e| format!("{}: {}", log, e))?;
+    if matches.is_present("fast") {
+        source = source.as_fast_as_possible();
+    } else {
+        let speed = matches.value_of("speed").unwrap_or("1");
+        let speed = speed
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid speed {:?}", speed))?;
+        source = source.speed(speed);
+    }
+    if let Some(from) = matches.value_of("from") {
+        source = source.start(parse_apple_time(from).map_err(|e| format!("--from: {}", e))?);
+    }
+    if let Some(until) = matches.value_of("until") {
+        source = source.end(parse_apple_time(until).map_err(|e| format!("--until: {}", e))?);
+    }
+    Ok(source)
 }
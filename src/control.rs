@@ -0,0 +1,314 @@
+use crate::disk::DiskIdentifier;
+use crate::event::{Event, EventKind};
+use crate::std::{Action, Error};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// What a paused monitor does with the events it keeps reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PausePolicy {
+    /// Keeps up to the given amount of the newest events, delivered on
+    /// [`Action::Resume`] or [`Action::Flush`]
+    ///
+    /// [`Action::Resume`]: ./enum.Action.html#variant.Resume
+    /// [`Action::Flush`]: ./enum.Action.html#variant.Flush
+    Buffer(usize),
+    /// Discards every event until resumed
+    Drop,
+}
+
+/// Which events a monitor forwards, changed at runtime with
+/// [`Action::SetFilter`]. Errors are always forwarded.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::{Event, EventFilter, EventKind};
+///
+/// let filter = EventFilter::all()
+///     .kinds(vec![EventKind::DiskAppeared, EventKind::DiskDisappeared])
+///     .disks(vec!["disk4"]);
+///
+/// let appeared = Event::parse("***DiskAppeared ('disk4s2', DAVolumePath = '<null>') Time=20220108-20:22:05.1438").unwrap();
+/// let peek = Event::parse("***DiskPeek ('disk4s2') Time=20220108-20:22:05.1438").unwrap();
+/// assert!(filter.matches(&appeared));
+/// assert!(!filter.matches(&peek));
+/// ```
+///
+/// [`Action::SetFilter`]: ./enum.Action.html#variant.SetFilter
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    kinds: Option<Vec<EventKind>>,
+    disks: Option<Vec<String>>,
+}
+
+impl EventFilter {
+    /// Forwards every event
+    pub fn all() -> EventFilter {
+        EventFilter::default()
+    }
+    /// Only forwards events of the given kinds
+    pub fn kinds<I: IntoIterator<Item = EventKind>>(mut self, kinds: I) -> EventFilter {
+        self.kinds = Some(kinds.into_iter().collect());
+        self
+    }
+    /// Only forwards events of the given disks or their slices, e.g.
+    /// `disk4` also matches `disk4s2`
+    pub fn disks<I, S>(mut self, disks: I) -> EventFilter
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.disks = Some(disks.into_iter().map(Into::into).collect());
+        self
+    }
+    /// Whether the event is forwarded
+    pub fn matches(&self, event: &Event) -> bool {
+        let kind_matches = match &self.kinds {
+            Some(kinds) => kinds.contains(&event.name()),
+            None => true,
+        };
+        let disk_matches = match &self.disks {
+            Some(disks) => event
+                .disk_identifier()
+                .is_some_and(|identifier| disks.iter().any(|disk| is_on_disk(&identifier, disk))),
+            None => true,
+        };
+        kind_matches && disk_matches
+    }
+}
+
+fn is_on_disk(identifier: &DiskIdentifier, disk: &str) -> bool {
+    let mut current = Some(identifier.clone());
+    while let Some(identifier) = current {
+        if identifier.to_string() == disk {
+            return true;
+        }
+        current = identifier.parent();
+    }
+    false
+}
+
+/// A snapshot of a running monitor, answered to [`Action::Status`].
+///
+/// [`Action::Status`]: ./enum.Action.html#variant.Status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorStatus {
+    /// The process id of the command, if any
+    pub pid: Option<u32>,
+    /// How long the monitor has been running
+    pub uptime: Duration,
+    /// How many non-blank lines were read
+    pub lines_read: u64,
+    /// How many events and errors were forwarded
+    pub forwarded: u64,
+    /// How many events did not match the filter
+    pub filtered: u64,
+    /// How many events were discarded while paused
+    pub dropped: u64,
+    /// How many events wait to be delivered
+    pub buffered: usize,
+    /// Whether the monitor is paused
+    pub paused: bool,
+    /// The last error read, if any
+    pub last_error: Option<String>,
+}
+
+/// What to do after applying an [`Action`]
+pub(crate) enum Applied {
+    Stop,
+    /// Send these items right away
+    Deliver(Vec<Result<Event, Error>>),
+}
+
+/// The state that [`Action`]s change within a reader
+pub(crate) struct Control {
+    pid: Option<u32>,
+    started: Instant,
+    pause: Option<PausePolicy>,
+    buffer: VecDeque<Result<Event, Error>>,
+    filter: EventFilter,
+    lines_read: u64,
+    forwarded: u64,
+    filtered: u64,
+    dropped: u64,
+    last_error: Option<String>,
+}
+
+impl Control {
+    pub(crate) fn new(pid: Option<u32>) -> Control {
+        Control {
+            pid,
+            started: Instant::now(),
+            pause: None,
+            buffer: VecDeque::new(),
+            filter: EventFilter::all(),
+            lines_read: 0,
+            forwarded: 0,
+            filtered: 0,
+            dropped: 0,
+            last_error: None,
+        }
+    }
+    pub(crate) fn apply(&mut self, action: Action) -> Applied {
+        match action {
+            Action::Stop => return Applied::Stop,
+            Action::Noop => {}
+            Action::Pause(policy) => {
+                self.pause = Some(policy);
+                self.trim_buffer();
+            }
+            Action::Resume => {
+                self.pause = None;
+                return Applied::Deliver(self.take_buffer());
+            }
+            Action::Flush => return Applied::Deliver(self.take_buffer()),
+            Action::SetFilter(filter) => self.filter = filter,
+            Action::Status(reply) => {
+                let _ = reply.send(self.status());
+            }
+        }
+        Applied::Deliver(Vec::new())
+    }
+    /// Counts a line read from the source and decides what happens to
+    /// what it became: `Some` to send it now, `None` when held back
+    pub(crate) fn admit(&mut self, item: Result<Event, Error>) -> Option<Result<Event, Error>> {
+        self.lines_read += 1;
+        match &item {
+            Ok(event) if !self.filter.matches(event) => {
+                self.filtered += 1;
+                return None;
+            }
+            Ok(_) => {}
+            Err(e) => self.last_error = Some(e.to_string()),
+        }
+        match self.pause {
+            None => {
+                self.forwarded += 1;
+                Some(item)
+            }
+            Some(PausePolicy::Drop) => {
+                self.dropped += 1;
+                None
+            }
+            Some(PausePolicy::Buffer(_)) => {
+                self.buffer.push_back(item);
+                self.trim_buffer();
+                None
+            }
+        }
+    }
+    /// What is still buffered, e.g. once the source ended
+    pub(crate) fn take_buffer(&mut self) -> Vec<Result<Event, Error>> {
+        self.forwarded += self.buffer.len() as u64;
+        self.buffer.drain(..).collect()
+    }
+    pub(crate) fn status(&self) -> MonitorStatus {
+        MonitorStatus {
+            pid: self.pid,
+            uptime: self.started.elapsed(),
+            lines_read: self.lines_read,
+            forwarded: self.forwarded,
+            filtered: self.filtered,
+            dropped: self.dropped,
+            buffered: self.buffer.len(),
+            paused: self.pause.is_some(),
+            last_error: self.last_error.clone(),
+        }
+    }
+    fn trim_buffer(&mut self) {
+        let capacity = match self.pause {
+            Some(PausePolicy::Buffer(capacity)) => capacity,
+            _ => return,
+        };
+        while self.buffer.len() > capacity {
+            self.buffer.pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Applied, Control, EventFilter, PausePolicy};
+    use crate::event::{Event, EventKind};
+    use crate::std::{Action, Error};
+    use k9::assert_equal;
+    use std::sync::mpsc::channel;
+
+    fn peek(disk: &str) -> Result<Event, Error> {
+        Ok(Event::parse(&format!(
+            "***DiskPeek ('{}') Time=20220108-20:22:35.8607",
+            disk
+        ))
+        .unwrap())
+    }
+
+    fn delivered(applied: Applied) -> Vec<String> {
+        match applied {
+            Applied::Deliver(items) => items
+                .into_iter()
+                .map(|item| item.unwrap().bsd_name().unwrap())
+                .collect(),
+            Applied::Stop => panic!("unexpected stop"),
+        }
+    }
+
+    #[test]
+    fn test_buffering_pause_keeps_the_newest_events() {
+        let mut control = Control::new(None);
+        delivered(control.apply(Action::Pause(PausePolicy::Buffer(2))));
+        for disk in ["disk1", "disk2", "disk3"] {
+            assert_equal!(control.admit(peek(disk)).is_none(), true);
+        }
+        assert_equal!(
+            delivered(control.apply(Action::Flush)),
+            vec!["disk2", "disk3"]
+        );
+        assert_equal!(control.admit(peek("disk4")).is_none(), true);
+        assert_equal!(delivered(control.apply(Action::Resume)), vec!["disk4"]);
+        assert_equal!(control.admit(peek("disk5")).is_some(), true);
+
+        let status = control.status();
+        assert_equal!(status.lines_read, 5);
+        assert_equal!(status.forwarded, 4);
+        assert_equal!(status.dropped, 1);
+        assert_equal!(status.paused, false);
+    }
+    #[test]
+    fn test_dropping_pause() {
+        let mut control = Control::new(Some(42));
+        delivered(control.apply(Action::Pause(PausePolicy::Drop)));
+        assert_equal!(control.admit(peek("disk1")).is_none(), true);
+        assert_equal!(
+            delivered(control.apply(Action::Resume)),
+            Vec::<String>::new()
+        );
+
+        let (reply, status) = channel();
+        delivered(control.apply(Action::Status(reply)));
+        let status = status.recv().unwrap();
+        assert_equal!(status.pid, Some(42));
+        assert_equal!(status.dropped, 1);
+    }
+    #[test]
+    fn test_filter_applies_to_events_only() {
+        let mut control = Control::new(None);
+        let filter = EventFilter::all().kinds(vec![EventKind::DiskAppeared]);
+        delivered(control.apply(Action::SetFilter(filter)));
+        assert_equal!(control.admit(peek("disk1")).is_none(), true);
+        assert_equal!(control.admit(Err(Error::SendError)).is_some(), true);
+
+        let status = control.status();
+        assert_equal!(status.filtered, 1);
+        assert_equal!(status.last_error, Some(Error::SendError.to_string()));
+    }
+    #[test]
+    fn test_filter_by_whole_disk() {
+        let filter = EventFilter::all().disks(vec!["disk4"]);
+        assert_equal!(filter.matches(peek("disk4s2s1").as_ref().unwrap()), true);
+        assert_equal!(filter.matches(peek("disk4").as_ref().unwrap()), true);
+        assert_equal!(filter.matches(peek("disk41").as_ref().unwrap()), false);
+        assert_equal!(filter.matches(&Event::empty()), false);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::event::Event;
+use crate::parser::ParseOptions;
+use crate::std::Error;
+use chrono::Utc;
+use std::string::FromUtf8Error;
+
+/// How the bytes printed by `diskutil activity` are turned into text.
+///
+/// `diskutil` prints UTF-8, but volume names of old FAT sticks may hold
+/// bytes of a legacy codepage.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::Decoding;
+///
+/// let name = b"Cl\x8e USB".to_vec();
+/// assert!(Decoding::Strict.decode(name.clone()).is_err());
+/// assert_eq!(Decoding::Lossy.decode(name.clone()).unwrap(), ("Cl\u{fffd} USB".to_string(), true));
+/// assert_eq!(Decoding::MacRoman.decode(name).unwrap(), ("Clé USB".to_string(), false));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Decoding {
+    /// Reports lines that are not valid UTF-8 as [`Error::Utf8`], which
+    /// holds the offending bytes
+    ///
+    /// [`Error::Utf8`]: ./enum.Error.html#variant.Utf8
+    #[default]
+    Strict,
+    /// Replaces invalid UTF-8 sequences with `U+FFFD` and flags the
+    /// event, see [`Event::is_lossy()`]
+    ///
+    /// [`Event::is_lossy()`]: ./struct.Event.html#method.is_lossy
+    Lossy,
+    /// Decodes lines that are not valid UTF-8 as Mac OS Roman, which
+    /// never fails
+    MacRoman,
+}
+
+impl Decoding {
+    /// Decodes a line, also returning whether characters were replaced
+    pub fn decode(self, bytes: Vec<u8>) -> Result<(String, bool), FromUtf8Error> {
+        match String::from_utf8(bytes) {
+            Ok(text) => Ok((text, false)),
+            Err(e) => match self {
+                Decoding::Strict => Err(e),
+                Decoding::Lossy => Ok((String::from_utf8_lossy(e.as_bytes()).into_owned(), true)),
+                Decoding::MacRoman => Ok((decode_mac_roman(e.as_bytes()), false)),
+            },
+        }
+    }
+}
+
+/// Decodes bytes in the Mac OS Roman codepage
+pub fn decode_mac_roman(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| match byte {
+            0x00..=0x7f => char::from(*byte),
+            _ => MAC_ROMAN[usize::from(byte - 0x80)],
+        })
+        .collect()
+}
+
+/// Decodes and parses a line read from a source, stamping when it was received
+pub(crate) fn parse_bytes(line: Vec<u8>, options: &ParseOptions) -> Result<Event, Error> {
+    let received_at = Utc::now();
+    let (line, lossy) = options.decoding.decode(line)?;
+    match Event::parse_with(line.as_str(), options) {
+        Ok(mut event) => {
+            event.set_received_at(received_at);
+            event.set_lossy(lossy);
+            Ok(event)
+        }
+        Err(source) => Err(Error::Parse { line, source }),
+    }
+}
+
+/// The characters of the bytes `0x80` to `0xff` in Mac OS Roman
+const MAC_ROMAN: [char; 128] = [
+    '\u{00c4}', '\u{00c5}', '\u{00c7}', '\u{00c9}', '\u{00d1}', '\u{00d6}', '\u{00dc}', '\u{00e1}',
+    '\u{00e0}', '\u{00e2}', '\u{00e4}', '\u{00e3}', '\u{00e5}', '\u{00e7}', '\u{00e9}', '\u{00e8}',
+    '\u{00ea}', '\u{00eb}', '\u{00ed}', '\u{00ec}', '\u{00ee}', '\u{00ef}', '\u{00f1}', '\u{00f3}',
+    '\u{00f2}', '\u{00f4}', '\u{00f6}', '\u{00f5}', '\u{00fa}', '\u{00f9}', '\u{00fb}', '\u{00fc}',
+    '\u{2020}', '\u{00b0}', '\u{00a2}', '\u{00a3}', '\u{00a7}', '\u{2022}', '\u{00b6}', '\u{00df}',
+    '\u{00ae}', '\u{00a9}', '\u{2122}', '\u{00b4}', '\u{00a8}', '\u{2260}', '\u{00c6}', '\u{00d8}',
+    '\u{221e}', '\u{00b1}', '\u{2264}', '\u{2265}', '\u{00a5}', '\u{00b5}', '\u{2202}', '\u{2211}',
+    '\u{220f}', '\u{03c0}', '\u{222b}', '\u{00aa}', '\u{00ba}', '\u{03a9}', '\u{00e6}', '\u{00f8}',
+    '\u{00bf}', '\u{00a1}', '\u{00ac}', '\u{221a}', '\u{0192}', '\u{2248}', '\u{2206}', '\u{00ab}',
+    '\u{00bb}', '\u{2026}', '\u{00a0}', '\u{00c0}', '\u{00c3}', '\u{00d5}', '\u{0152}', '\u{0153}',
+    '\u{2013}', '\u{2014}', '\u{201c}', '\u{201d}', '\u{2018}', '\u{2019}', '\u{00f7}', '\u{25ca}',
+    '\u{00ff}', '\u{0178}', '\u{2044}', '\u{20ac}', '\u{2039}', '\u{203a}', '\u{fb01}', '\u{fb02}',
+    '\u{2021}', '\u{00b7}', '\u{201a}', '\u{201e}', '\u{2030}', '\u{00c2}', '\u{00ca}', '\u{00c1}',
+    '\u{00cb}', '\u{00c8}', '\u{00cd}', '\u{00ce}', '\u{00cf}', '\u{00cc}', '\u{00d3}', '\u{00d4}',
+    '\u{f8ff}', '\u{00d2}', '\u{00da}', '\u{00db}', '\u{00d9}', '\u{0131}', '\u{02c6}', '\u{02dc}',
+    '\u{00af}', '\u{02d8}', '\u{02d9}', '\u{02da}', '\u{00b8}', '\u{02dd}', '\u{02db}', '\u{02c7}',
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_mac_roman, Decoding};
+    use k9::assert_equal;
+
+    #[test]
+    fn test_valid_utf8_is_kept_in_every_mode() {
+        for decoding in [Decoding::Strict, Decoding::Lossy, Decoding::MacRoman] {
+            assert_equal!(
+                decoding.decode("Clé".as_bytes().to_vec()).unwrap(),
+                (String::from("Clé"), false)
+            );
+        }
+    }
+    #[test]
+    fn test_strict_keeps_the_offending_bytes() {
+        let error = Decoding::Strict.decode(b"EFI\xff".to_vec()).unwrap_err();
+        assert_equal!(error.as_bytes(), b"EFI\xff");
+        assert_equal!(error.utf8_error().valid_up_to(), 3);
+    }
+    #[test]
+    fn test_mac_roman() {
+        assert_equal!(decode_mac_roman(b"\x80\x8e\xa5\xdb\xff"), "Äé•€ˇ");
+        assert_equal!(decode_mac_roman(b"plain"), "plain");
+    }
+}
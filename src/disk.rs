@@ -0,0 +1,228 @@
+use crate::parser::{ParseError, ParseErrorKind};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// A BSD disk identifier such as `disk3`, `disk3s1` or the APFS-style `disk1s5s1`.
+///
+/// Identifiers are ordered by whole disk number and then by slice
+/// numbers, so a whole disk sorts right before its partitions.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::DiskIdentifier;
+///
+/// let snapshot: DiskIdentifier = "disk1s5s1".parse().unwrap();
+/// assert_eq!(snapshot.disk(), 1);
+/// assert_eq!(snapshot.slices(), &[5, 1]);
+/// assert_eq!(snapshot.parent().unwrap().to_string(), "disk1s5");
+/// assert_eq!(snapshot.whole_disk().to_string(), "disk1");
+/// assert!(snapshot.whole_disk().is_whole());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DiskIdentifier {
+    disk: u32,
+    slices: Vec<u32>,
+}
+
+impl DiskIdentifier {
+    /// Creates a new DiskIdentifier from a whole disk number and slice numbers
+    pub fn new(disk: u32, slices: Vec<u32>) -> DiskIdentifier {
+        DiskIdentifier { disk, slices }
+    }
+    /// The number of the whole disk, e.g. `3` for `disk3s1`
+    pub fn disk(&self) -> u32 {
+        self.disk
+    }
+    /// The slice numbers, e.g. `[5, 1]` for `disk1s5s1`
+    pub fn slices(&self) -> &[u32] {
+        &self.slices
+    }
+    /// Whether this is a whole disk rather than a slice of one
+    pub fn is_whole(&self) -> bool {
+        self.slices.is_empty()
+    }
+    /// The identifier this slice belongs to, `None` for whole disks
+    pub fn parent(&self) -> Option<DiskIdentifier> {
+        let (_, parent) = self.slices.split_last()?;
+        Some(DiskIdentifier::new(self.disk, parent.to_vec()))
+    }
+    /// The whole disk this identifier belongs to
+    pub fn whole_disk(&self) -> DiskIdentifier {
+        DiskIdentifier::new(self.disk, Vec::new())
+    }
+    /// Whether this identifier is a slice, directly or not, of the given one
+    pub fn is_descendant_of(&self, ancestor: &DiskIdentifier) -> bool {
+        self.disk == ancestor.disk
+            && self.slices.len() > ancestor.slices.len()
+            && self.slices.starts_with(&ancestor.slices)
+    }
+}
+
+impl FromStr for DiskIdentifier {
+    type Err = ParseError;
+
+    fn from_str(name: &str) -> Result<DiskIdentifier, ParseError> {
+        let invalid =
+            |offset: usize| ParseError::new(ParseErrorKind::DiskRef, offset, "invalid BSD name");
+        let rest = name.strip_prefix("disk").ok_or_else(|| invalid(0))?;
+        let mut numbers = rest.split('s');
+        let mut offset = "disk".len();
+        let mut parse_number = |number: Option<&str>| -> Result<u32, ParseError> {
+            let number = number.unwrap_or_default();
+            if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid(offset));
+            }
+            let parsed = number.parse().map_err(|_| invalid(offset))?;
+            offset += number.len() + 1;
+            Ok(parsed)
+        };
+        let disk = parse_number(numbers.next())?;
+        let slices = numbers
+            .map(|number| parse_number(Some(number)))
+            .collect::<Result<Vec<u32>, ParseError>>()?;
+        Ok(DiskIdentifier::new(disk, slices))
+    }
+}
+
+impl TryFrom<String> for DiskIdentifier {
+    type Error = ParseError;
+
+    fn try_from(name: String) -> Result<DiskIdentifier, ParseError> {
+        name.parse()
+    }
+}
+
+impl From<DiskIdentifier> for String {
+    fn from(identifier: DiskIdentifier) -> String {
+        identifier.to_string()
+    }
+}
+
+impl fmt::Display for DiskIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "disk{}", self.disk)?;
+        for slice in &self.slices {
+            write!(f, "s{}", slice)?;
+        }
+        Ok(())
+    }
+}
+
+/// How an event refers to its disk.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiskRef {
+    /// A disk with a BSD name, printed as `'disk3s1'`
+    Bsd(DiskIdentifier),
+    /// A disk without a BSD name, e.g. an autofs mount, printed as `(no BSD name)`
+    NoBsdName,
+    /// No disk at all, e.g. `DAIdle`, printed as `no DADiskRef`
+    NoDiskRef,
+}
+
+impl DiskRef {
+    /// The BSD disk identifier, if any
+    pub fn identifier(&self) -> Option<&DiskIdentifier> {
+        match self {
+            DiskRef::Bsd(identifier) => Some(identifier),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiskIdentifier, DiskRef};
+    use crate::parser::ParseErrorKind;
+    use k9::assert_equal;
+
+    fn id(name: &str) -> DiskIdentifier {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_disk_identifiers() {
+        assert_equal!(id("disk3"), DiskIdentifier::new(3, vec![]));
+        assert_equal!(id("disk3s1"), DiskIdentifier::new(3, vec![1]));
+        assert_equal!(id("disk1s5s1"), DiskIdentifier::new(1, vec![5, 1]));
+        assert_equal!(id("disk12s10").to_string(), "disk12s10");
+    }
+    #[test]
+    fn test_parse_invalid_disk_identifiers() {
+        for (name, offset) in [
+            ("no DADiskRef", 0),
+            ("disk", 4),
+            ("disks1", 4),
+            ("disk3s", 6),
+            ("disk3x1", 4),
+            ("disk3s1s", 8),
+        ] {
+            let error = name.parse::<DiskIdentifier>().unwrap_err();
+            assert_equal!(
+                (name, error.kind(), error.offset()),
+                (name, ParseErrorKind::DiskRef, offset)
+            );
+        }
+    }
+    #[test]
+    fn test_parent_and_whole_disk() {
+        assert_equal!(id("disk3").parent(), None);
+        assert_equal!(id("disk3s1").parent(), Some(id("disk3")));
+        assert_equal!(id("disk1s5s1").parent(), Some(id("disk1s5")));
+        assert_equal!(id("disk1s5s1").whole_disk(), id("disk1"));
+        assert_equal!(id("disk1s5s1").is_descendant_of(&id("disk1")), true);
+        assert_equal!(id("disk1s5s1").is_descendant_of(&id("disk1s5")), true);
+        assert_equal!(id("disk1s5").is_descendant_of(&id("disk1s5")), false);
+        assert_equal!(id("disk1s5").is_descendant_of(&id("disk11")), false);
+    }
+    #[test]
+    fn test_ordering_groups_partitions_by_disk() {
+        let mut identifiers = [
+            id("disk2"),
+            id("disk1s5s1"),
+            id("disk10s1"),
+            id("disk1s5"),
+            id("disk1"),
+            id("disk1s10"),
+            id("disk1s2"),
+        ];
+        identifiers.sort();
+
+        assert_equal!(
+            identifiers
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "disk1",
+                "disk1s2",
+                "disk1s5",
+                "disk1s5s1",
+                "disk1s10",
+                "disk2",
+                "disk10s1"
+            ]
+        );
+    }
+    #[test]
+    fn test_disk_ref_serde() {
+        assert_equal!(
+            serde_json::to_string(&DiskRef::Bsd(id("disk3s1"))).unwrap(),
+            r#"{"bsd":"disk3s1"}"#
+        );
+        assert_equal!(
+            serde_json::to_string(&DiskRef::NoDiskRef).unwrap(),
+            r#""no_disk_ref""#
+        );
+        let parsed: DiskRef = serde_json::from_str(r#""no_bsd_name""#).unwrap();
+        assert_equal!(parsed, DiskRef::NoBsdName);
+        assert_equal!(
+            serde_json::from_str::<DiskRef>(r#"{"bsd":"sda1"}"#).is_err(),
+            true
+        );
+    }
+}
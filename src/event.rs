@@ -1,8 +1,119 @@
-use chrono::{Local, NaiveDateTime};
-use regex::Regex;
+use crate::approval::ApprovalOutcome;
+use crate::attributes::{
+    Attributes, DA_DEVICE_PROTOCOL, DA_MEDIA_NAME, DA_MEDIA_PATH, DA_MEDIA_REMOVABLE,
+    DA_MEDIA_SIZE, DA_VOLUME_KIND, DA_VOLUME_NAME, DA_VOLUME_PATH, DA_VOLUME_UUID, NULL_VALUE,
+};
+use crate::disk::{DiskIdentifier, DiskRef};
+use crate::parser::{parse_line, DiskRefToken, ParseError, ParseOptions};
+use crate::time::{format_apple_time, parse_apple_time, resolve_time};
+use crate::volume::{VolumeKind, VolumePath};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, Offset, Utc};
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
 
-const APPLE_FORMAT: &'static str = "%Y%m%d-%H:%M:%S.%f";
+/// The DiskArbitration callback that produced an [`Event`].
+///
+/// Names that are not known to this crate are kept verbatim in
+/// [`EventKind::Unknown`] so that nothing printed by `diskutil activity`
+/// is lost.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::EventKind;
+///
+/// assert_eq!(EventKind::from("DiskAppeared"), EventKind::DiskAppeared);
+/// assert_eq!(EventKind::from("DAIdle").as_str(), "DAIdle");
+/// assert_eq!(EventKind::from("DiskFoo"), EventKind::Unknown(String::from("DiskFoo")));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+#[non_exhaustive]
+pub enum EventKind {
+    DiskAppeared,
+    DiskDisappeared,
+    DiskDescriptionChanged,
+    DiskPeek,
+    DiskMountApproval,
+    DiskUnmountApproval,
+    DiskEjectApproval,
+    DAIdle,
+    Unknown(String),
+}
+
+impl EventKind {
+    /// The name of the event as printed by `diskutil activity`
+    pub fn as_str(&self) -> &str {
+        match self {
+            EventKind::DiskAppeared => "DiskAppeared",
+            EventKind::DiskDisappeared => "DiskDisappeared",
+            EventKind::DiskDescriptionChanged => "DiskDescriptionChanged",
+            EventKind::DiskPeek => "DiskPeek",
+            EventKind::DiskMountApproval => "DiskMountApproval",
+            EventKind::DiskUnmountApproval => "DiskUnmountApproval",
+            EventKind::DiskEjectApproval => "DiskEjectApproval",
+            EventKind::DAIdle => "DAIdle",
+            EventKind::Unknown(name) => name.as_str(),
+        }
+    }
+    /// Whether this is one of the `Disk*Approval` callbacks, which carry a `Comment=`
+    pub fn is_approval(&self) -> bool {
+        matches!(
+            self,
+            EventKind::DiskMountApproval
+                | EventKind::DiskUnmountApproval
+                | EventKind::DiskEjectApproval
+        )
+    }
+}
+
+impl From<&str> for EventKind {
+    fn from(name: &str) -> EventKind {
+        match name {
+            "DiskAppeared" => EventKind::DiskAppeared,
+            "DiskDisappeared" => EventKind::DiskDisappeared,
+            "DiskDescriptionChanged" => EventKind::DiskDescriptionChanged,
+            "DiskPeek" => EventKind::DiskPeek,
+            "DiskMountApproval" => EventKind::DiskMountApproval,
+            "DiskUnmountApproval" => EventKind::DiskUnmountApproval,
+            "DiskEjectApproval" => EventKind::DiskEjectApproval,
+            "DAIdle" => EventKind::DAIdle,
+            other => EventKind::Unknown(String::from(other)),
+        }
+    }
+}
+
+impl From<String> for EventKind {
+    fn from(name: String) -> EventKind {
+        EventKind::from(name.as_str())
+    }
+}
+
+impl From<EventKind> for String {
+    fn from(kind: EventKind) -> String {
+        kind.as_str().to_string()
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialEq<str> for EventKind {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for EventKind {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
 
 /// Structured data about a disk event.
 ///
@@ -11,81 +122,155 @@ const APPLE_FORMAT: &'static str = "%Y%m%d-%H:%M:%S.%f";
 /// # Example:
 ///
 /// ```
-/// use mac_disk_monitor::Event;
+/// use mac_disk_monitor::{Event, EventKind};
 ///
-/// let event = Event::from_line("***DiskAppeared ('disk3s1', DAVolumePath = '<null>', DAVolumeKind = 'msdos', DAVolumeName = 'EFI') Time=20220108-20:22:05.000001454");
-/// assert_eq!(event.name(), "DiskAppeared");
+/// let event = Event::parse("***DiskAppeared ('disk3s1', DAVolumePath = '<null>', DAVolumeKind = 'msdos', DAVolumeName = 'EFI') Time=20220108-20:22:05.1454").unwrap();
+/// assert_eq!(event.name(), EventKind::DiskAppeared);
 /// assert_eq!(event.bsd_name().unwrap(), "disk3s1");
+/// assert_eq!(event.disk_identifier().unwrap().parent().unwrap().to_string(), "disk3");
 /// assert_eq!(event.kind().unwrap(), "msdos");
 /// assert_eq!(event.volume_name().unwrap(), "EFI");
+/// assert_eq!(event.time_string(), "20220108-20:22:05.1454");
 /// ```
+///
+/// The volume path, kind and name are serialized only once, as the
+/// `DAVolumePath`, `DAVolumeKind` and `DAVolumeName` attributes, and
+/// their typed forms are rebuilt from there when deserializing.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "EventData")]
 pub struct Event {
-    name: String,
-    time: NaiveDateTime,
-    bsd_name: Option<String>,
-    volume_path: Option<String>,
-    volume_kind: Option<String>,
+    name: EventKind,
+    time: DateTime<FixedOffset>,
+    received_at: DateTime<Utc>,
+    disk_ref: DiskRef,
+    #[serde(skip)]
+    volume_path: Option<VolumePath>,
+    #[serde(skip)]
+    volume_kind: Option<VolumeKind>,
+    #[serde(skip)]
     volume_name: Option<String>,
     comment: Option<String>,
+    #[serde(default)]
+    attributes: Attributes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    raw: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unparsed: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    lossy: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+}
+
+/// The serialized fields of an [`Event`], without the typed volume data
+#[derive(Deserialize)]
+struct EventData {
+    name: EventKind,
+    time: DateTime<FixedOffset>,
+    received_at: DateTime<Utc>,
+    disk_ref: DiskRef,
+    comment: Option<String>,
+    #[serde(default)]
+    attributes: Attributes,
+    #[serde(default)]
+    raw: Option<String>,
+    #[serde(default)]
+    unparsed: Vec<String>,
+    #[serde(default)]
+    lossy: bool,
+    #[serde(default)]
+    host: Option<String>,
+}
+
+impl From<EventData> for Event {
+    fn from(data: EventData) -> Event {
+        let mut event = Event {
+            name: data.name,
+            time: data.time,
+            received_at: data.received_at,
+            disk_ref: data.disk_ref,
+            volume_path: None,
+            volume_kind: None,
+            volume_name: None,
+            comment: data.comment,
+            attributes: Attributes::new(),
+            raw: data.raw,
+            unparsed: data.unparsed,
+            lossy: data.lossy,
+            host: data.host,
+        };
+        for (key, value) in data.attributes.iter() {
+            event.set_attribute(key, value.unwrap_or(NULL_VALUE));
+        }
+        event
+    }
 }
 
 impl Event {
     /// Creates an empty Event
     pub fn empty() -> Event {
         Event {
-            name: String::new(),
-            time: Local::now().naive_local(),
-            bsd_name: None,
+            name: EventKind::Unknown(String::new()),
+            time: local_now(),
+            received_at: Utc::now(),
+            disk_ref: DiskRef::NoDiskRef,
             volume_path: None,
             volume_kind: None,
             volume_name: None,
             comment: None,
+            attributes: Attributes::new(),
+            raw: None,
+            unparsed: Vec::new(),
+            lossy: false,
+            host: None,
         }
     }
-    /// Creates a new Event containing the parsed the disk information from the given line.
-    pub fn from_line(line: &str) -> Event {
+    /// Parses the disk information from the given line.
+    ///
+    /// Trailing newline characters are ignored. Returns a
+    /// [`ParseError`] describing which part of the line is invalid.
+    ///
+    /// The `Time=` value is interpreted in the local time zone, use
+    /// [`Event::parse_with()`] to change that.
+    ///
+    /// [`ParseError`]: ./struct.ParseError.html
+    pub fn parse(line: &str) -> Result<Event, ParseError> {
+        Event::parse_with(line, &ParseOptions::default())
+    }
+    /// Parses the disk information from the given line with the given options.
+    pub fn parse_with(line: &str, options: &ParseOptions) -> Result<Event, ParseError> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        let raw = parse_line(line)?;
         let mut event = Event::empty();
-        //\s*\(('(?P<bsd_name>[^']+)')?, DAVolumePath\s*=\s*(?P<path>'[^']+')\)
-        match extract_base_metadata(line) {
-            Some((name, bsd_name, comment, time)) => {
-                event.set_name(name.as_str());
-                match bsd_name {
-                    Some(bsd_name) => {
-                        event.set_bsd_name(bsd_name.as_str());
-                    }
-                    None => {}
-                }
-                match comment {
-                    Some(comment) => {
-                        event.set_comment(comment.as_str());
-                    }
-                    None => {}
-                }
-                event.set_time_string(time.as_str());
-            }
-            None => {}
-        }
-        match extract_volume_path(line) {
-            Some(path) => {
-                event.set_path(path.as_str());
-            }
-            None => {}
+        event.set_name(raw.name);
+        match raw.disk_ref {
+            DiskRefToken::Bsd(bsd_name) => event
+                .set_bsd_name(&bsd_name)
+                .map_err(|e| e.shifted(raw.disk_ref_offset + 1))?,
+            DiskRefToken::NoBsdName => event.set_disk_ref(DiskRef::NoBsdName),
+            DiskRefToken::NoDiskRef => event.set_disk_ref(DiskRef::NoDiskRef),
         }
-        match extract_volume_kind(line) {
-            Some(kind) => {
-                event.set_kind(kind.as_str());
-            }
-            None => {}
+        for (key, value) in raw.attributes {
+            event.set_attribute(key, &value);
         }
-        match extract_volume_name(line) {
-            Some(name) => {
-                event.set_volume_name(name.as_str());
-            }
-            None => {}
+        if let Some(comment) = raw.comment {
+            event.set_comment(comment);
         }
-
         event
+            .set_time_string_with(raw.time, options)
+            .map_err(|e| e.shifted(raw.time_offset))?;
+        if options.keep_raw {
+            event.raw = Some(String::from(line));
+            event.unparsed = raw.trailing.map(String::from).into_iter().collect();
+        }
+        Ok(event)
+    }
+    /// Creates a new Event containing the parsed the disk information from the given line.
+    ///
+    /// Lines that cannot be parsed result in an [`Event::empty()`],
+    /// use [`Event::parse()`] to find out why parsing failed.
+    pub fn from_line(line: &str) -> Event {
+        Event::parse(line).unwrap_or_else(|_| Event::empty())
     }
     /// Serializes the Event to a yaml string
     pub fn to_yaml(&self) -> String
@@ -107,23 +292,79 @@ impl Event {
             Err(e) => format!("{}", e),
         }
     }
+    /// Renders the Event the way `diskutil activity` prints it, the
+    /// inverse of [`Event::parse()`].
+    ///
+    /// Attributes are printed in their original order with `<null>` for
+    /// absent values, and `Time=` is printed in the offset of
+    /// [`Event::time()`] with 4 fractional digits, or more when needed
+    /// to keep its precision. Values are printed verbatim, without
+    /// escaping, as `diskutil` does.
+    ///
+    /// The result is byte-exact for lines as `diskutil` prints them.
+    /// Other lines are normalized: a `Time=` fraction such as `.14` is
+    /// printed as `.1400`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use mac_disk_monitor::Event;
+    ///
+    /// let line = "***DiskAppeared ((no BSD name), DAVolumePath = 'file:///Volumes/share%20(2)/', DAVolumeKind = 'smbfs', DAVolumeName = '<null>') Time=20220108-20:22:05.1457";
+    /// assert_eq!(Event::parse(line).unwrap().to_diskutil_line(), line);
+    /// ```
+    pub fn to_diskutil_line(&self) -> String {
+        let mut line = format!("***{} (", self.name);
+        match &self.disk_ref {
+            DiskRef::Bsd(identifier) => line.push_str(&format!("'{}'", identifier)),
+            DiskRef::NoBsdName => line.push_str("(no BSD name)"),
+            DiskRef::NoDiskRef => line.push_str("no DADiskRef"),
+        }
+        for (key, value) in self.attributes.iter() {
+            line.push_str(&format!(", {} = '{}'", key, value.unwrap_or(NULL_VALUE)));
+        }
+        line.push(')');
+        if let Some(comment) = &self.comment {
+            line.push_str(&format!(" Comment={}", comment));
+        }
+        line.push_str(&format!(" Time={}", self.time_string()));
+        for fragment in &self.unparsed {
+            line.push(' ');
+            line.push_str(fragment);
+        }
+        line
+    }
 
     /// The event name
-    pub fn name(&self) -> String {
+    pub fn name(&self) -> EventKind {
         self.name.clone()
     }
 
     /// Sets the event name
     pub fn set_name(&mut self, name: &str) {
-        self.name = String::from(name)
+        self.name = EventKind::from(name)
     }
-    /// Sets the bsd_name
-    pub fn set_bsd_name(&mut self, bsd_name: &str) {
-        self.bsd_name = Some(String::from(bsd_name));
+    /// Sets the bsd_name, e.g. `disk3s1`
+    pub fn set_bsd_name(&mut self, bsd_name: &str) -> Result<(), ParseError> {
+        self.disk_ref = DiskRef::Bsd(bsd_name.parse()?);
+        Ok(())
     }
     /// The disk name, if any
     pub fn bsd_name(&self) -> Option<String> {
-        self.bsd_name.clone()
+        self.disk_identifier()
+            .map(|identifier| identifier.to_string())
+    }
+    /// Sets how the event refers to its disk
+    pub fn set_disk_ref(&mut self, disk_ref: DiskRef) {
+        self.disk_ref = disk_ref;
+    }
+    /// How the event refers to its disk
+    pub fn disk_ref(&self) -> DiskRef {
+        self.disk_ref.clone()
+    }
+    /// The BSD disk identifier, if any
+    pub fn disk_identifier(&self) -> Option<DiskIdentifier> {
+        self.disk_ref.identifier().cloned()
     }
     /// Sets the event comment
     pub fn set_comment(&mut self, comment: &str) {
@@ -133,31 +374,53 @@ impl Event {
     pub fn comment(&self) -> Option<String> {
         self.comment.clone()
     }
+    /// The outcome of a `Disk*Approval` event, parsed from its comment.
+    ///
+    /// `None` for other events or when there is no comment. The full
+    /// comment is still available via [`Event::comment()`].
+    pub fn approval(&self) -> Option<ApprovalOutcome> {
+        if !self.name.is_approval() {
+            return None;
+        }
+        self.comment.as_deref().map(ApprovalOutcome::parse)
+    }
 
     /// Sets the volume path of the event
+    ///
+    /// Values that are not a valid URL are still kept in the
+    /// attributes, but [`Event::volume_path()`] returns `None` for them.
     pub fn set_path(&mut self, path: &str) {
-        self.volume_path = if !path.eq("<null>") {
-            Some(String::from(path))
-        } else {
-            None
-        };
+        self.volume_path = VolumePath::parse(path).ok();
+        self.attributes.insert(DA_VOLUME_PATH, path);
     }
-    /// The volume path, if any
+    /// The volume path URL as printed by `diskutil activity`, if any
     pub fn path(&self) -> Option<String> {
-        self.volume_path.clone()
+        match &self.volume_path {
+            Some(path) => Some(String::from(path.url())),
+            None => self.attribute(DA_VOLUME_PATH),
+        }
+    }
+    /// The parsed volume path, if any
+    pub fn volume_path(&self) -> Option<&VolumePath> {
+        self.volume_path.as_ref()
     }
 
     /// Sets the volume kind, if any
     pub fn set_kind(&mut self, kind: &str) {
         self.volume_kind = if !kind.eq("<null>") {
-            Some(String::from(kind))
+            Some(VolumeKind::from(kind))
         } else {
             None
         };
+        self.attributes.insert(DA_VOLUME_KIND, kind);
     }
     /// The volume kind, if any
     pub fn kind(&self) -> Option<String> {
-        self.volume_kind.clone()
+        self.volume_kind.as_ref().map(|kind| kind.to_string())
+    }
+    /// The typed volume kind, if any
+    pub fn volume_kind(&self) -> Option<&VolumeKind> {
+        self.volume_kind.as_ref()
     }
     /// Sets the volume name
     pub fn set_volume_name(&mut self, name: &str) {
@@ -166,24 +429,158 @@ impl Event {
         } else {
             None
         };
+        self.attributes.insert(DA_VOLUME_NAME, name);
     }
     /// The volume name, if any
     pub fn volume_name(&self) -> Option<String> {
         self.volume_name.clone()
     }
-    pub fn set_time_string(&mut self, time: &str) {
-        match NaiveDateTime::parse_from_str(time, APPLE_FORMAT) {
-            Ok(time) => {
-                self.time = time;
-            }
-            Err(e) => {
-                panic!("Warning: {}", e);
-            }
+    /// Sets the value of a `DADiskDescription` key, `<null>` meaning absent
+    pub fn set_attribute(&mut self, key: &str, value: &str) {
+        match key {
+            DA_VOLUME_PATH => self.set_path(value),
+            DA_VOLUME_KIND => self.set_kind(value),
+            DA_VOLUME_NAME => self.set_volume_name(value),
+            _ => self.attributes.insert(key, value),
         }
     }
-    /// The time when the event happened as string
+    /// The value of a `DADiskDescription` key, if present and not `<null>`
+    pub fn attribute(&self, key: &str) -> Option<String> {
+        self.attributes.get(key).map(String::from)
+    }
+    /// Every `DADiskDescription` key printed for the event, in order
+    pub fn attributes(&self) -> &Attributes {
+        &self.attributes
+    }
+    /// The volume UUID, if any
+    pub fn volume_uuid(&self) -> Option<String> {
+        self.attribute(DA_VOLUME_UUID)
+    }
+    /// The media name, if any
+    pub fn media_name(&self) -> Option<String> {
+        self.attribute(DA_MEDIA_NAME)
+    }
+    /// The media size in bytes, if any
+    pub fn media_size(&self) -> Option<u64> {
+        self.attributes.get_u64(DA_MEDIA_SIZE)
+    }
+    /// Whether the media is removable, if known
+    pub fn media_removable(&self) -> Option<bool> {
+        self.attributes.get_bool(DA_MEDIA_REMOVABLE)
+    }
+    /// The IOKit path of the media, if any
+    pub fn media_path(&self) -> Option<String> {
+        self.attribute(DA_MEDIA_PATH)
+    }
+    /// The device protocol, e.g. `USB`, if any
+    pub fn device_protocol(&self) -> Option<String> {
+        self.attribute(DA_DEVICE_PROTOCOL)
+    }
+    /// Sets the time of the event from a string in Apple's format,
+    /// interpreted in the local time zone
+    pub fn set_time_string(&mut self, time: &str) -> Result<(), ParseError> {
+        self.set_time_string_with(time, &ParseOptions::default())
+    }
+    /// Sets the time of the event from a string in Apple's format,
+    /// interpreted according to the given options
+    pub fn set_time_string_with(
+        &mut self,
+        time: &str,
+        options: &ParseOptions,
+    ) -> Result<(), ParseError> {
+        let naive = parse_apple_time(time)?;
+        self.time = resolve_time(&naive, options.timezone, options.ambiguous_time)?;
+        Ok(())
+    }
+    /// The time when the event happened as string in Apple's format
     pub fn time_string(&self) -> String {
-        self.time.format(APPLE_FORMAT).to_string()
+        format_apple_time(&self.time.naive_local())
+    }
+    /// Sets the time when the event happened
+    pub fn set_time(&mut self, time: DateTime<FixedOffset>) {
+        self.time = time;
+    }
+    /// The time when the event happened, in the time zone it was printed in
+    pub fn time(&self) -> DateTime<FixedOffset> {
+        self.time
+    }
+    /// The time when the event happened, in UTC
+    pub fn time_utc(&self) -> DateTime<Utc> {
+        self.time.with_timezone(&Utc)
+    }
+    /// The wall-clock time printed by `diskutil activity`
+    pub fn naive_time(&self) -> NaiveDateTime {
+        self.time.naive_local()
+    }
+    /// Sets the time when the line of the event was read
+    pub fn set_received_at(&mut self, received_at: DateTime<Utc>) {
+        self.received_at = received_at;
+    }
+    /// The time when the line of the event was read
+    pub fn received_at(&self) -> DateTime<Utc> {
+        self.received_at
+    }
+    /// How long it took between the event happening and its line being read
+    pub fn lag(&self) -> chrono::Duration {
+        self.received_at.signed_duration_since(self.time_utc())
+    }
+    /// The line this event was parsed from, when parsed with
+    /// [`ParseOptions::keep_raw`]
+    ///
+    /// [`ParseOptions::keep_raw`]: ./struct.ParseOptions.html#structfield.keep_raw
+    pub fn raw(&self) -> Option<&str> {
+        self.raw.as_deref()
+    }
+    /// The fragments of the line that are not part of the grammar, e.g.
+    /// text after the `Time=` value, when parsed with [`ParseOptions::keep_raw`]
+    ///
+    /// [`ParseOptions::keep_raw`]: ./struct.ParseOptions.html#structfield.keep_raw
+    pub fn unparsed(&self) -> &[String] {
+        &self.unparsed
+    }
+    /// Sets whether invalid UTF-8 in the line was replaced
+    pub fn set_lossy(&mut self, lossy: bool) {
+        self.lossy = lossy;
+    }
+    /// Whether the line held invalid UTF-8 that was replaced with
+    /// `U+FFFD`, see [`Decoding::Lossy`]
+    ///
+    /// [`Decoding::Lossy`]: ./enum.Decoding.html#variant.Lossy
+    pub fn is_lossy(&self) -> bool {
+        self.lossy
+    }
+    /// Sets the host the event happened on
+    pub fn set_host(&mut self, host: &str) {
+        self.host = Some(String::from(host));
+    }
+    /// The host the event happened on, when monitored through a
+    /// [`Transport`] or labeled with [`MonitorBuilder::host_label()`]
+    ///
+    /// [`Transport`]: ./struct.Transport.html
+    /// [`MonitorBuilder::host_label()`]: ./struct.MonitorBuilder.html#method.host_label
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+}
+
+fn local_now() -> DateTime<FixedOffset> {
+    let now = Local::now();
+    now.with_timezone(&now.offset().fix())
+}
+
+impl FromStr for Event {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Event, ParseError> {
+        Event::parse(line)
+    }
+}
+
+impl TryFrom<&str> for Event {
+    type Error = ParseError;
+
+    fn try_from(line: &str) -> Result<Event, ParseError> {
+        Event::parse(line)
     }
 }
 
@@ -194,80 +591,68 @@ impl Event {
 /// - time
 pub fn extract_base_metadata(
     line: &str,
-) -> Option<(String, Option<String>, Option<String>, String)> {
-    let re =
-        Regex::new(r"^[*]{3}(\w+)\s*\('?([^,']+)'?.*?\)\s*(Comment=(\S+))?\s*Time=(\S+)").unwrap();
-    match re.captures(line) {
-        Some(caps) => {
-            let name = caps.get(1).unwrap().as_str().to_string();
-            let bsd_name = caps.get(2).unwrap().as_str().to_string();
-            let bsd_name = if bsd_name.eq("(no BSD name)") {
-                None
-            } else {
-                Some(bsd_name)
-            };
-            let comment = match caps.get(4) {
-                Some(m) => Some(m.as_str().to_string()),
-                None => None,
-            };
-            let time = caps.get(5).unwrap().as_str().to_string();
-            Some((name, bsd_name, comment, time))
-        }
-        None => None,
-    }
+) -> Option<(EventKind, Option<String>, Option<String>, String)> {
+    let raw = parse_line(line).ok()?;
+    let bsd_name = match raw.disk_ref {
+        DiskRefToken::Bsd(bsd_name) => Some(bsd_name.into_owned()),
+        DiskRefToken::NoBsdName | DiskRefToken::NoDiskRef => None,
+    };
+    Some((
+        EventKind::from(raw.name),
+        bsd_name,
+        raw.comment.map(String::from),
+        String::from(raw.time),
+    ))
+}
+/// Extracts the raw value of the given `DADiskDescription` key from the line.
+fn extract_attribute(line: &str, key: &str) -> Option<String> {
+    parse_line(line).ok()?.attribute(key).map(String::from)
 }
 /// Extracts the volume path from the given line.
 pub fn extract_volume_path(line: &str) -> Option<String> {
-    let re = Regex::new(r"DAVolumePath\s*=\s*('([^']+)')").unwrap();
-
-    match re.captures(line) {
-        Some(caps) => {
-            let name = caps.get(2).unwrap().as_str().to_string();
-            Some(name)
-        }
-        None => None,
-    }
+    extract_attribute(line, "DAVolumePath")
 }
 /// Extracts the volume kind from the given line.
 pub fn extract_volume_kind(line: &str) -> Option<String> {
-    let re = Regex::new(r"DAVolumeKind\s*=\s*('([^']+)')").unwrap();
-
-    match re.captures(line) {
-        Some(caps) => {
-            let name = caps.get(2).unwrap().as_str().to_string();
-            Some(name)
-        }
-        None => None,
-    }
+    extract_attribute(line, "DAVolumeKind")
 }
 /// Extracts the volume name from the given line.
 pub fn extract_volume_name(line: &str) -> Option<String> {
-    let re = Regex::new(r"DAVolumeName\s*=\s*('([^']+)')").unwrap();
-
-    match re.captures(line) {
-        Some(caps) => {
-            let name = caps.get(2).unwrap().as_str().to_string();
-            Some(name)
-        }
-        None => None,
-    }
+    extract_attribute(line, "DAVolumeName")
 }
 #[cfg(test)]
 mod tests {
-    use super::{extract_base_metadata, Event};
+    use super::{extract_base_metadata, Event, EventKind};
+    use crate::approval::{ApprovalOutcome, Dissent};
+    use crate::disk::{DiskIdentifier, DiskRef};
+    use crate::parser::{ParseErrorKind, ParseOptions};
+    use crate::time::EventTimeZone;
+    use chrono::{FixedOffset, Utc};
     use k9::assert_equal;
+    use std::convert::TryFrom;
+
+    fn utc() -> ParseOptions {
+        ParseOptions {
+            timezone: EventTimeZone::Utc,
+            ..ParseOptions::default()
+        }
+    }
 
     #[test]
     fn test_parse_disk_appeared_with_volume_path() {
-        let line = String::from("***DiskAppeared ('disk4', DAVolumePath = 'file:///Volumes/my%20backups/', DAVolumeKind = 'hfs', DAVolumeName = 'Time Machine Backups') Time=20220108-20:22:05.000001438");
+        let line = String::from("***DiskAppeared ('disk4', DAVolumePath = 'file:///Volumes/my%20backups/', DAVolumeKind = 'hfs', DAVolumeName = 'Time Machine Backups') Time=20220108-20:22:05.1438");
         let disk_appeared = Event::from_line(line.as_str());
 
-        assert_equal!(disk_appeared.name().as_str(), "DiskAppeared");
+        assert_equal!(disk_appeared.name(), EventKind::DiskAppeared);
         assert_equal!(disk_appeared.bsd_name(), Some(String::from("disk4")));
         assert_equal!(
             disk_appeared.path(),
             Some(String::from("file:///Volumes/my%20backups/"))
         );
+        assert_equal!(
+            disk_appeared.volume_path().map(|path| path.path()),
+            Some(std::path::Path::new("/Volumes/my backups"))
+        );
         assert_equal!(disk_appeared.kind(), Some(String::from("hfs")));
         assert_equal!(
             disk_appeared.volume_name(),
@@ -275,31 +660,32 @@ mod tests {
         );
         assert_equal!(
             disk_appeared.time_string().as_str(),
-            "20220108-20:22:05.000001438"
+            "20220108-20:22:05.1438"
         );
     }
     #[test]
     fn test_parse_disk_appeared_without_volume_path_kind_and_name() {
-        let line = String::from("***DiskAppeared ('disk3s2', DAVolumePath = '<null>', DAVolumeKind = '<null>', DAVolumeName = '<null>') Time=20220108-20:22:05.000001453");
+        let line = String::from("***DiskAppeared ('disk3s2', DAVolumePath = '<null>', DAVolumeKind = '<null>', DAVolumeName = '<null>') Time=20220108-20:22:05.1453");
         let disk_appeared = Event::from_line(line.as_str());
 
-        assert_equal!(disk_appeared.name().as_str(), "DiskAppeared");
+        assert_equal!(disk_appeared.name(), EventKind::DiskAppeared);
         assert_equal!(disk_appeared.bsd_name(), Some(String::from("disk3s2")));
         assert_equal!(disk_appeared.path(), None);
         assert_equal!(disk_appeared.kind(), None);
         assert_equal!(disk_appeared.volume_name(), None);
         assert_equal!(
             disk_appeared.time_string().as_str(),
-            "20220108-20:22:05.000001453"
+            "20220108-20:22:05.1453"
         );
     }
     #[test]
     fn test_parse_disk_appeared_without_bsd_name() {
-        let line = String::from("***DiskAppeared ((no BSD name), DAVolumePath = 'file:///System/Volumes/Data/home/', DAVolumeKind = 'autofs', DAVolumeName = '<null>') Time=20220108-20:22:05.000001457");
+        let line = String::from("***DiskAppeared ((no BSD name), DAVolumePath = 'file:///System/Volumes/Data/home/', DAVolumeKind = 'autofs', DAVolumeName = '<null>') Time=20220108-20:22:05.1457");
         let disk_appeared = Event::from_line(line.as_str());
 
-        assert_equal!(disk_appeared.name().as_str(), "DiskAppeared");
+        assert_equal!(disk_appeared.name(), EventKind::DiskAppeared);
         assert_equal!(disk_appeared.bsd_name(), None);
+        assert_equal!(disk_appeared.disk_ref(), DiskRef::NoBsdName);
         assert_equal!(
             disk_appeared.path(),
             Some(String::from("file:///System/Volumes/Data/home/"))
@@ -308,74 +694,144 @@ mod tests {
         assert_equal!(disk_appeared.volume_name(), None);
         assert_equal!(
             disk_appeared.time_string().as_str(),
-            "20220108-20:22:05.000001457"
+            "20220108-20:22:05.1457"
         );
     }
     #[test]
     fn test_parse_disk_disappeared() {
-        let line = String::from("***DiskDisappeared ('disk3s1', DAVolumePath = '<null>', DAVolumeKind = 'msdos', DAVolumeName = 'EFI') Time=20220108-20:22:29.000006773");
+        let line = String::from("***DiskDisappeared ('disk3s1', DAVolumePath = '<null>', DAVolumeKind = 'msdos', DAVolumeName = 'EFI') Time=20220108-20:22:29.6773");
         let event = Event::from_line(line.as_str());
 
-        assert_equal!(event.name().as_str(), "DiskDisappeared");
+        assert_equal!(event.name(), EventKind::DiskDisappeared);
         assert_equal!(event.bsd_name(), Some(String::from("disk3s1")));
         assert_equal!(event.path(), None);
         assert_equal!(event.kind(), Some(String::from("msdos")));
         assert_equal!(event.volume_name(), Some(String::from("EFI")));
-        assert_equal!(event.time_string().as_str(), "20220108-20:22:29.000006773");
+        assert_equal!(event.time_string().as_str(), "20220108-20:22:29.6773");
     }
     #[test]
     fn test_parse_disk_mount_approval() {
-        let line = String::from("***DiskMountApproval ('disk3s1', DAVolumePath = '<null>', DAVolumeKind = 'msdos', DAVolumeName = 'EFI') Comment=Approving Time=20220108-20:22:35.000008686");
+        let line = String::from("***DiskMountApproval ('disk3s1', DAVolumePath = '<null>', DAVolumeKind = 'msdos', DAVolumeName = 'EFI') Comment=Approving Time=20220108-20:22:35.8686");
         let event = Event::from_line(line.as_str());
 
-        assert_equal!(event.name().as_str(), "DiskMountApproval");
+        assert_equal!(event.name(), EventKind::DiskMountApproval);
         assert_equal!(event.bsd_name(), Some(String::from("disk3s1")));
         assert_equal!(event.path(), None);
         assert_equal!(event.kind(), Some(String::from("msdos")));
         assert_equal!(event.volume_name(), Some(String::from("EFI")));
         assert_equal!(event.comment(), Some(String::from("Approving")));
-        assert_equal!(event.time_string().as_str(), "20220108-20:22:35.000008686");
+        assert_equal!(event.approval(), Some(ApprovalOutcome::Approving));
+        assert_equal!(event.time_string().as_str(), "20220108-20:22:35.8686");
     }
     #[test]
     fn test_parse_disk_peek() {
-        let line = String::from("***DiskPeek ('disk3s1') Time=20220108-20:22:35.000008607");
+        let line = String::from("***DiskPeek ('disk3s1') Time=20220108-20:22:35.8607");
         let event = Event::from_line(line.as_str());
 
-        assert_equal!(event.name().as_str(), "DiskPeek");
+        assert_equal!(event.name(), EventKind::DiskPeek);
         assert_equal!(event.bsd_name(), Some(String::from("disk3s1")));
-        assert_equal!(event.time_string().as_str(), "20220108-20:22:35.000008607");
+        assert_equal!(event.time_string().as_str(), "20220108-20:22:35.8607");
     }
     #[test]
     fn test_parse_disk_activity_idle() {
-        let line = String::from("***DAIdle (no DADiskRef) Time=20220108-20:22:29.000006774");
+        let line = String::from("***DAIdle (no DADiskRef) Time=20220108-20:22:29.6774");
         let event = Event::from_line(line.as_str());
 
-        assert_equal!(event.name().as_str(), "DAIdle");
-        assert_equal!(event.bsd_name(), Some(String::from("no DADiskRef")));
-        assert_equal!(event.time_string().as_str(), "20220108-20:22:29.000006774");
+        assert_equal!(event.name(), EventKind::DAIdle);
+        assert_equal!(event.bsd_name(), None);
+        assert_equal!(event.disk_ref(), DiskRef::NoDiskRef);
+        assert_equal!(event.time_string().as_str(), "20220108-20:22:29.6774");
+    }
+
+    #[test]
+    fn test_parse_unknown_event_kind() {
+        let line = String::from("***DiskFrobnicated ('disk3s1') Time=20220108-20:22:35.8607");
+        let event = Event::from_line(line.as_str());
+
+        assert_equal!(
+            event.name(),
+            EventKind::Unknown(String::from("DiskFrobnicated"))
+        );
+        assert_equal!(event.name(), "DiskFrobnicated");
+        assert_equal!(
+            event.to_json().contains(r#""name":"DiskFrobnicated""#),
+            true
+        );
+    }
+    #[test]
+    fn test_event_kind_serde() {
+        assert_equal!(
+            serde_json::to_string(&EventKind::DiskEjectApproval).unwrap(),
+            r#""DiskEjectApproval""#
+        );
+        let kind: EventKind = serde_json::from_str(r#""DAIdle""#).unwrap();
+        assert_equal!(kind, EventKind::DAIdle);
+        assert_equal!(EventKind::DiskUnmountApproval.is_approval(), true);
+        assert_equal!(EventKind::DiskPeek.is_approval(), false);
+    }
+
+    #[test]
+    fn test_parse_time_with_options() {
+        let line = "***DiskPeek ('disk3s1') Time=20220108-20:22:35.8607";
+        let options = ParseOptions {
+            timezone: EventTimeZone::Fixed(FixedOffset::east_opt(3600).unwrap()),
+            ..ParseOptions::default()
+        };
+        let event = Event::parse_with(line, &options).unwrap();
+
+        assert_equal!(
+            event.time().to_rfc3339(),
+            "2022-01-08T20:22:35.860700+01:00"
+        );
+        assert_equal!(
+            event.time_utc().to_rfc3339(),
+            "2022-01-08T19:22:35.860700+00:00"
+        );
+        assert_equal!(event.time().timestamp_subsec_millis(), 860);
+        assert_equal!(event.time_string().as_str(), "20220108-20:22:35.8607");
+    }
+    #[test]
+    fn test_received_at_and_lag() {
+        let line = "***DiskPeek ('disk3s1') Time=20220108-20:22:35.8607";
+        let options = ParseOptions {
+            timezone: EventTimeZone::Utc,
+            ..ParseOptions::default()
+        };
+        let before = Utc::now();
+        let mut event = Event::parse_with(line, &options).unwrap();
+
+        assert_equal!(event.received_at() >= before, true);
+        event.set_received_at("2022-01-08T20:22:36Z".parse().unwrap());
+        assert_equal!(event.lag().num_microseconds(), Some(139_300));
     }
 
     #[test]
     fn test_event_to_yaml() {
         let mut event = Event::empty();
         event.set_name("EventName");
-        event.set_bsd_name("disk1337");
+        event.set_bsd_name("disk1337").unwrap();
         event.set_volume_name("LeetDisk");
         event.set_path("file:///foo/bar");
         event.set_kind("stub");
         event.set_comment("dummy");
-        event.set_time_string("20130307-13:37:42.1337");
+        event
+            .set_time_string_with("20130307-13:37:42.1337", &utc())
+            .unwrap();
+        event.set_received_at("2013-03-07T13:37:43Z".parse().unwrap());
 
         assert_equal!(
             event.to_yaml(),
             r#"---
 name: EventName
-time: "2013-03-07T13:37:42.000001337"
-bsd_name: disk1337
-volume_path: "file:///foo/bar"
-volume_kind: stub
-volume_name: LeetDisk
+time: "2013-03-07T13:37:42.133700+00:00"
+received_at: "2013-03-07T13:37:43Z"
+disk_ref:
+  bsd: disk1337
 comment: dummy
+attributes:
+  DAVolumeName: LeetDisk
+  DAVolumePath: "file:///foo/bar"
+  DAVolumeKind: stub
 "#
         );
     }
@@ -383,17 +839,169 @@ comment: dummy
     fn test_event_to_json() {
         let mut event = Event::empty();
         event.set_name("EventName");
-        event.set_bsd_name("disk1337");
+        event.set_bsd_name("disk1337").unwrap();
         event.set_volume_name("LeetDisk");
         event.set_path("file:///foo/bar");
         event.set_kind("stub");
         event.set_comment("dummy");
-        event.set_time_string("20130307-13:37:42.1337");
+        event
+            .set_time_string_with("20130307-13:37:42.1337", &utc())
+            .unwrap();
+        event.set_received_at("2013-03-07T13:37:43Z".parse().unwrap());
 
         assert_equal!(
             event.to_json(),
-            r#"{"name":"EventName","time":"2013-03-07T13:37:42.000001337","bsd_name":"disk1337","volume_path":"file:///foo/bar","volume_kind":"stub","volume_name":"LeetDisk","comment":"dummy"}"#
+            r#"{"name":"EventName","time":"2013-03-07T13:37:42.133700+00:00","received_at":"2013-03-07T13:37:43Z","disk_ref":{"bsd":"disk1337"},"comment":"dummy","attributes":{"DAVolumeName":"LeetDisk","DAVolumePath":"file:///foo/bar","DAVolumeKind":"stub"}}"#
+        );
+    }
+    #[test]
+    fn test_parse_disk_eject_dissent() {
+        let line = "***DiskEjectApproval ('disk4', DAVolumePath = 'file:///Volumes/STICK/', DAVolumeKind = 'msdos', DAVolumeName = 'STICK') Comment=Dissenting (status = 0x0000c010, string = 'Volume in use', process = 4242 Finder) Time=20220108-20:22:35.8686";
+        let event = Event::parse(line).unwrap();
+
+        assert_equal!(
+            event.comment().unwrap(),
+            "Dissenting (status = 0x0000c010, string = 'Volume in use', process = 4242 Finder)"
+        );
+        assert_equal!(
+            event.approval(),
+            Some(ApprovalOutcome::Dissenting(Dissent {
+                status: Some(0xc010),
+                message: Some(String::from("Volume in use")),
+                process: Some(String::from("4242 Finder")),
+            }))
+        );
+
+        let mut appeared = event.clone();
+        appeared.set_name("DiskAppeared");
+        assert_equal!(appeared.approval(), None);
+    }
+    #[test]
+    fn test_parse_keeping_raw_line() {
+        let line = "***DiskPeek ('disk3s1', DAVolumeKind = 'apfs') Time=20220108-20:22:05.1454 (retry 2)\n";
+        let options = ParseOptions {
+            keep_raw: true,
+            ..utc()
+        };
+        let event = Event::parse_with(line, &options).unwrap();
+
+        assert_equal!(
+            event.raw(),
+            Some("***DiskPeek ('disk3s1', DAVolumeKind = 'apfs') Time=20220108-20:22:05.1454 (retry 2)")
+        );
+        assert_equal!(event.unparsed(), &[String::from("(retry 2)")][..]);
+        assert_equal!(
+            event.to_json().contains(r#""unparsed":["(retry 2)"]"#),
+            true
+        );
+
+        let lean = Event::parse_with(line, &utc()).unwrap();
+        assert_equal!(lean.raw(), None);
+        assert_equal!(lean.unparsed().is_empty(), true);
+        assert_equal!(lean.to_json().contains("raw"), false);
+    }
+    #[test]
+    fn test_parse_keeps_every_attribute_in_order() {
+        let line = "***DiskAppeared ('disk5s1', DAMediaName = 'SanDisk Cruzer', DAVolumePath = 'file:///Volumes/STICK/', DAVolumeUUID = '<null>', DAMediaSize = '31914983424', DAMediaRemovable = 'true', DADeviceProtocol = 'USB', DAMediaPath = 'IODeviceTree:/PCI0@0/XHC1@14', DAVolumeKind = 'msdos', DAVolumeName = 'STICK') Time=20220108-20:22:05.1454";
+        let event = Event::parse(line).unwrap();
+
+        assert_equal!(
+            event.attributes().keys().collect::<Vec<_>>(),
+            vec![
+                "DAMediaName",
+                "DAVolumePath",
+                "DAVolumeUUID",
+                "DAMediaSize",
+                "DAMediaRemovable",
+                "DADeviceProtocol",
+                "DAMediaPath",
+                "DAVolumeKind",
+                "DAVolumeName",
+            ]
+        );
+        assert_equal!(event.media_name(), Some(String::from("SanDisk Cruzer")));
+        assert_equal!(event.volume_uuid(), None);
+        assert_equal!(event.attributes().contains_key("DAVolumeUUID"), true);
+        assert_equal!(event.media_size(), Some(31914983424));
+        assert_equal!(event.media_removable(), Some(true));
+        assert_equal!(event.device_protocol(), Some(String::from("USB")));
+        assert_equal!(
+            event.media_path(),
+            Some(String::from("IODeviceTree:/PCI0@0/XHC1@14"))
         );
+        assert_equal!(event.kind(), Some(String::from("msdos")));
+        assert_equal!(
+            event.to_json().contains(
+                r#""attributes":{"DAMediaName":"SanDisk Cruzer","DAVolumePath":"file:///Volumes/STICK/","DAVolumeUUID":null,"#
+            ),
+            true
+        );
+
+        let deserialized: Event = serde_json::from_str(event.to_json().as_str()).unwrap();
+        assert_equal!(deserialized, event);
+    }
+    #[test]
+    fn test_parse_apfs_snapshot_disk_identifier() {
+        let event = Event::parse("***DiskAppeared ('disk1s5s1', DAVolumePath = 'file:///', DAVolumeKind = 'apfs', DAVolumeName = 'Macintosh HD') Time=20220108-20:22:05.1469").unwrap();
+
+        assert_equal!(
+            event.disk_ref(),
+            DiskRef::Bsd(DiskIdentifier::new(1, vec![5, 1]))
+        );
+        assert_equal!(event.bsd_name(), Some(String::from("disk1s5s1")));
+    }
+    #[test]
+    fn test_parse_errors_report_the_failing_part_and_offset() {
+        let cases =
+            vec![
+            ("DiskPeek ('disk3s1') Time=20220108-20:22:35.8607", ParseErrorKind::Prefix, 0),
+            ("*** ('disk3s1') Time=20220108-20:22:35.8607", ParseErrorKind::EventName, 3),
+            ("***DiskPeek disk3s1 Time=20220108-20:22:35.8607", ParseErrorKind::DiskRef, 12),
+            ("***DiskPeek ('disk3s1) Time=20220108-20:22:35.8607", ParseErrorKind::DiskRef, 50),
+            ("***DiskPeek ('disk3z1') Time=20220108-20:22:35.8607", ParseErrorKind::DiskRef, 18),
+            (
+                "***DiskAppeared ('disk3s1', DAVolumeKind = msdos) Time=20220108-20:22:35.8607",
+                ParseErrorKind::KeyValueList,
+                43,
+            ),
+            (
+                "***DiskAppeared ('disk3s1' DAVolumeKind = 'msdos') Time=20220108-20:22:35.8607",
+                ParseErrorKind::KeyValueList,
+                27,
+            ),
+            ("***DiskMountApproval ('disk3s1') Comment=Approving", ParseErrorKind::Comment, 41),
+            ("***DiskPeek ('disk3s1')", ParseErrorKind::Timestamp, 23),
+            ("***DiskPeek ('disk3s1') Time=20220108", ParseErrorKind::Timestamp, 29),
+        ];
+        for (line, kind, offset) in cases {
+            let error = Event::parse(line).unwrap_err();
+            assert_equal!((line, error.kind(), error.offset()), (line, kind, offset));
+        }
+    }
+    #[test]
+    fn test_parse_ignores_trailing_newline() {
+        let event = Event::parse("***DAIdle (no DADiskRef) Time=20220108-20:22:29.6774\n").unwrap();
+
+        assert_equal!(event.name(), EventKind::DAIdle);
+        assert_equal!(event.time_string().as_str(), "20220108-20:22:29.6774");
+    }
+    #[test]
+    fn test_from_str_and_try_from() {
+        let line = "***DiskPeek ('disk3s1') Time=20220108-20:22:35.8607";
+        let parsed: Event = line.parse().unwrap();
+        let mut converted = Event::try_from(line).unwrap();
+        converted.set_received_at(parsed.received_at());
+
+        assert_equal!(converted, parsed);
+        assert_equal!(parsed.bsd_name(), Some(String::from("disk3s1")));
+        assert_equal!("invalid event log".parse::<Event>().is_err(), true);
+    }
+    #[test]
+    fn test_from_line_does_not_panic_on_malformed_time() {
+        let event = Event::from_line("***DiskPeek ('disk3s1') Time=not-a-time");
+
+        assert_equal!(event.name(), "");
+        assert_equal!(event.bsd_name(), None);
     }
     #[test]
     fn test_extract_base_metadata_invalid() {
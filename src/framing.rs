@@ -0,0 +1,148 @@
+use crate::source::EventSource;
+use std::io::{self, ErrorKind};
+
+/// The banner `diskutil activity` prints before any event
+pub(crate) const BANNER_PREFIX: &str = "***Begin monitoring";
+
+/// What [`LineReader::next_line()`] found
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Frame {
+    /// A complete line, without its line terminator
+    Line(Vec<u8>),
+    /// The read was interrupted before a line was complete
+    TimedOut,
+    /// The source has nothing left
+    End,
+}
+
+/// Splits what an [`EventSource`] reads into complete lines.
+///
+/// Bytes read before an interruption are kept until the rest of their line
+/// arrives, a last line without newline is still returned at the end,
+/// and blank lines and the `***Begin monitoring` banner are skipped.
+pub(crate) struct LineReader<S> {
+    source: S,
+    partial: Vec<u8>,
+}
+
+impl<S: EventSource> LineReader<S> {
+    pub(crate) fn new(source: S) -> LineReader<S> {
+        LineReader {
+            source,
+            partial: Vec::new(),
+        }
+    }
+    pub(crate) fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+    /// Reads until a meaningful line is complete, the read is interrupted
+    /// or the source ends
+    pub(crate) fn next_line(&mut self) -> io::Result<Frame> {
+        loop {
+            let line = match self.source.read_line(&mut self.partial) {
+                Ok(0) if self.partial.is_empty() => return Ok(Frame::End),
+                // the last line may lack a newline
+                Ok(0) => std::mem::take(&mut self.partial),
+                Ok(_) if self.partial.ends_with(b"\n") => std::mem::take(&mut self.partial),
+                Ok(_) => continue,
+                Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                    return Ok(Frame::TimedOut)
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let line = trim_line_end(line);
+            if is_meaningful(&line) {
+                return Ok(Frame::Line(line));
+            }
+        }
+    }
+}
+
+/// The line without its line terminator
+pub(crate) fn trim_line_end(mut line: Vec<u8>) -> Vec<u8> {
+    while matches!(line.last(), Some(b'\n' | b'\r')) {
+        line.pop();
+    }
+    line
+}
+
+/// Whether the line is neither blank nor the banner
+pub(crate) fn is_meaningful(line: &[u8]) -> bool {
+    !line.iter().all(u8::is_ascii_whitespace) && !line.starts_with(BANNER_PREFIX.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Frame, LineReader};
+    use crate::source::EventSource;
+    use k9::assert_equal;
+    use std::collections::VecDeque;
+    use std::io::{self, ErrorKind};
+
+    /// Hands out the given chunks one read at a time, timing out in between
+    struct Chunks {
+        chunks: VecDeque<&'static str>,
+        timed_out: bool,
+    }
+
+    impl EventSource for Chunks {
+        fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize> {
+            self.timed_out = !self.timed_out;
+            if self.timed_out {
+                return Err(io::Error::new(ErrorKind::TimedOut, "nothing yet"));
+            }
+            match self.chunks.pop_front() {
+                Some(chunk) => {
+                    line.extend_from_slice(chunk.as_bytes());
+                    Ok(chunk.len())
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    fn frames(chunks: &[&'static str]) -> Vec<String> {
+        let mut reader = LineReader::new(Chunks {
+            chunks: chunks.iter().copied().collect(),
+            timed_out: false,
+        });
+        let mut lines = Vec::new();
+        loop {
+            match reader.next_line().unwrap() {
+                Frame::Line(line) => lines.push(String::from_utf8(line).unwrap()),
+                Frame::TimedOut => {}
+                Frame::End => return lines,
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_lines_survive_timeouts() {
+        assert_equal!(
+            frames(&[
+                "***Disk",
+                "Peek ('disk1') Ti",
+                "me=20220108-20:22:05.1438\n"
+            ]),
+            vec!["***DiskPeek ('disk1') Time=20220108-20:22:05.1438"]
+        );
+    }
+    #[test]
+    fn test_blank_lines_and_banner_are_skipped() {
+        assert_equal!(
+            frames(&[
+                "***Begin monitoring DiskArbitration activity\n",
+                "\n",
+                "  \r\n",
+                "a\r\n",
+            ]),
+            vec!["a"]
+        );
+    }
+    #[test]
+    fn test_last_line_without_newline() {
+        assert_equal!(frames(&["a\n", "b"]), vec!["a", "b"]);
+        assert_equal!(frames(&[]), Vec::<String>::new());
+    }
+}
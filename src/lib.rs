@@ -1,47 +1,75 @@
 //! Mac Disk Monitor
 //!
 //! This crate provides a way to monitor disk activity on MacOS in real time.
-//! Under the hood it simply calls `diskutil activity` in a thread and parses its output via [`Event::from_line()`]
+//! Under the hood it simply calls `diskutil activity` in a thread and parses its output via [`Event::from_line()`].
+//! The subprocess is killed when the [`EventStream`] is stopped or dropped.
 //!
 //!
 //! Example usage:
 //!
 //! ```no_run
-//! use mac_disk_monitor::{stream_events, Action};
-//! use std::sync::mpsc::channel;
+//! use mac_disk_monitor::{Action, EventStream};
 //! use std::time::Duration;
 //!
 //! fn main() {
-//!     let (action, receiver) = channel();
-//!     let (thread, receiver) = stream_events(receiver);
+//!     let mut stream = EventStream::new().unwrap();
+//!     let actions = stream.actions();
+//!     ctrlc::set_handler(move || actions.send(Action::Stop).unwrap()).unwrap();
 //!
-//!     loop {
-//!         // poll for events every 1.5 seconds
-//!         match receiver.recv_timeout(Duration::from_millis(1500)) {
-//!             Ok(event) => match event {
-//!                 Some(event) => {
-//!                     println!("{}", event.to_json());
-//!                 }
-//!                 None => {
-//!                     eprintln!("thread has stopped...");
-//!                 }
-//!             },
-//!             Err(e) => {
-//!                 eprintln!("Error: {}", e);
-//!                 break;
-//!             }
+//!     // poll for events every 1.5 seconds until the stream is stopped
+//!     while !stream.is_finished() {
+//!         match stream.next_timeout(Duration::from_millis(1500)) {
+//!             Some(Ok(event)) => println!("{}", event.to_json()),
+//!             Some(Err(e)) => eprintln!("Error: {}", e),
+//!             None => {}
 //!         }
 //!     }
-//!     action.send(Action::Stop).unwrap();
-//!     eprintln!("waiting for thread to stop...");
-//!     thread.join().unwrap().unwrap()
 //! }
 //! ```
 /// [`Event::from_line()`]: ./struct.Event.html#method.from_line
+/// [`EventStream`]: ./struct.EventStream.html
+pub mod approval;
+pub mod attributes;
+pub mod control;
+pub mod decoding;
+pub mod disk;
 pub mod event;
+mod framing;
+pub mod monitor;
+pub mod parser;
+#[cfg(unix)]
+pub mod pty;
+pub mod replay;
+pub mod shared;
+pub mod source;
 pub mod std;
+pub mod supervisor;
+pub mod time;
+#[cfg(feature = "async")]
+pub mod tokio;
+#[cfg(unix)]
+pub mod transport;
+pub mod volume;
+mod wake;
+pub use crate::approval::*;
+pub use crate::attributes::*;
+pub use crate::control::*;
+pub use crate::decoding::*;
+pub use crate::disk::*;
 pub use crate::event::*;
+pub use crate::monitor::*;
+pub use crate::parser::*;
+#[cfg(unix)]
+pub use crate::pty::*;
+pub use crate::replay::*;
+pub use crate::shared::*;
+pub use crate::source::*;
 pub use crate::std::*;
+pub use crate::supervisor::*;
+pub use crate::time::*;
+#[cfg(unix)]
+pub use crate::transport::*;
+pub use crate::volume::*;
 
 /// The version of the crate
 pub fn version() -> String {
@@ -0,0 +1,680 @@
+use crate::control::{Applied, Control, EventFilter, MonitorStatus, PausePolicy};
+use crate::decoding::parse_bytes;
+use crate::event::Event;
+use crate::framing::{Frame, LineReader};
+use crate::parser::ParseOptions;
+#[cfg(unix)]
+use crate::pty::{self, WindowSize};
+use crate::source::{CommandSource, EventSource, Interrupter};
+use crate::std::{Action, Error};
+#[cfg(unix)]
+use crate::transport::Transport;
+use crate::wake::Waker;
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{
+    channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError,
+};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How many of the last stderr lines of the command are kept
+pub const STDERR_TAIL_LINES: usize = 20;
+
+/// Configures and spawns a [`Monitor`].
+///
+/// Defaults to running `/usr/sbin/diskutil activity` like
+/// [`stream_events`].
+///
+/// # Example:
+///
+/// ```no_run
+/// use mac_disk_monitor::MonitorBuilder;
+///
+/// let monitor = MonitorBuilder::new()
+///     .channel_capacity(128)
+///     .build();
+///
+/// match monitor {
+///     Ok(monitor) => {
+///         for event in monitor {
+///             println!("{}", event.unwrap().to_json());
+///         }
+///     }
+///     Err(e) => eprintln!("cannot monitor disks: {}", e),
+/// }
+/// ```
+///
+/// [`Monitor`]: ./struct.Monitor.html
+/// [`stream_events`]: ./fn.stream_events.html
+#[derive(Debug, Clone)]
+pub struct MonitorBuilder {
+    command: OsString,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    current_dir: Option<PathBuf>,
+    channel_capacity: Option<usize>,
+    capture_stderr: bool,
+    #[cfg(unix)]
+    pty: Option<WindowSize>,
+    #[cfg(unix)]
+    transport: Transport,
+    host_label: Option<String>,
+    options: ParseOptions,
+}
+
+impl Default for MonitorBuilder {
+    fn default() -> MonitorBuilder {
+        MonitorBuilder {
+            command: OsString::from("/usr/sbin/diskutil"),
+            args: vec![OsString::from("activity")],
+            envs: Vec::new(),
+            current_dir: None,
+            channel_capacity: None,
+            capture_stderr: true,
+            #[cfg(unix)]
+            pty: None,
+            #[cfg(unix)]
+            transport: Transport::local(),
+            host_label: None,
+            options: ParseOptions::default(),
+        }
+    }
+}
+
+impl MonitorBuilder {
+    /// Creates a builder for `diskutil activity`
+    pub fn new() -> MonitorBuilder {
+        MonitorBuilder::default()
+    }
+    /// Runs the given command instead of `diskutil`, clearing the arguments
+    pub fn command<S: Into<OsString>>(mut self, command: S) -> MonitorBuilder {
+        self.command = command.into();
+        self.args.clear();
+        self
+    }
+    /// Appends an argument to the command
+    pub fn arg<S: Into<OsString>>(mut self, arg: S) -> MonitorBuilder {
+        self.args.push(arg.into());
+        self
+    }
+    /// Appends arguments to the command
+    pub fn args<I, S>(mut self, args: I) -> MonitorBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+    /// Sets an environment variable of the command
+    pub fn env<K: Into<OsString>, V: Into<OsString>>(mut self, key: K, value: V) -> MonitorBuilder {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+    /// Sets the working directory of the command
+    pub fn current_dir<P: Into<PathBuf>>(mut self, dir: P) -> MonitorBuilder {
+        self.current_dir = Some(dir.into());
+        self
+    }
+    /// Bounds the amount of events waiting to be received: the reader
+    /// stops reading once it is full. Unbounded by default.
+    pub fn channel_capacity(mut self, capacity: usize) -> MonitorBuilder {
+        self.channel_capacity = Some(capacity);
+        self
+    }
+    /// Whether to keep the last lines of the command's stderr, see
+    /// [`Monitor::stderr_tail()`]. Otherwise stderr is inherited.
+    /// Enabled by default.
+    ///
+    /// [`Monitor::stderr_tail()`]: ./struct.Monitor.html#method.stderr_tail
+    pub fn capture_stderr(mut self, capture: bool) -> MonitorBuilder {
+        self.capture_stderr = capture;
+        self
+    }
+    /// Runs the command on a pseudo-terminal of the given size instead of
+    /// a pipe, so that it line-buffers its stdout as it would in a
+    /// terminal. Terminal control sequences are stripped before parsing.
+    /// Stderr is still captured through a pipe.
+    #[cfg(unix)]
+    pub fn pty(mut self, size: WindowSize) -> MonitorBuilder {
+        self.pty = Some(size);
+        self
+    }
+    /// Runs the command through the given transport, e.g. on another
+    /// host with `ssh`. See [`supervise`] to reconnect once the
+    /// connection is lost.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use mac_disk_monitor::{supervise, MonitorBuilder, RestartPolicy, SupervisorEvent, Transport};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let builder = MonitorBuilder::new().transport(Transport::ssh("build-3.local"));
+    /// let (_action, receiver) = channel();
+    /// let (_thread, events) = supervise(builder, RestartPolicy::new().unlimited_restarts(), receiver);
+    /// for item in events {
+    ///     if let SupervisorEvent::Monitor(Ok(event)) = item {
+    ///         println!("{}: {}", event.host().unwrap(), event.to_json());
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`supervise`]: ./fn.supervise.html
+    #[cfg(unix)]
+    pub fn transport(mut self, transport: Transport) -> MonitorBuilder {
+        self.transport = transport;
+        self
+    }
+    /// Labels every event with the given host, instead of the host of
+    /// the transport, if any
+    pub fn host_label<S: Into<String>>(mut self, label: S) -> MonitorBuilder {
+        self.host_label = Some(label.into());
+        self
+    }
+    /// Sets how each line is parsed
+    pub fn parse_options(mut self, options: ParseOptions) -> MonitorBuilder {
+        self.options = options;
+        self
+    }
+    /// Spawns the command and the thread that parses its stdout
+    pub fn build(&self) -> Result<Monitor, Error> {
+        let (actions, action_receiver) = channel();
+        let parts = self.spawn(action_receiver)?;
+        Ok(Monitor::from_parts(actions, parts))
+    }
+    /// Monitors the given source instead of spawning the command, using
+    /// the timings, channel capacity and parse options of this builder
+    pub fn build_from_source<S: EventSource>(&self, source: S) -> Monitor {
+        let (actions, action_receiver) = channel();
+        Monitor::from_parts(actions, self.spawn_source(source, action_receiver))
+    }
+    pub(crate) fn spawn(&self, action: Receiver<Action>) -> Result<MonitorParts, Error> {
+        let source = self.command_source()?;
+        let stderr = source.stderr();
+        let mut parts = self.spawn_source(source, action);
+        parts.stderr = stderr;
+        Ok(parts)
+    }
+    /// Spawns the command as an [`EventSource`], e.g. to monitor it
+    /// alongside other sources
+    ///
+    /// [`EventSource`]: ./source/trait.EventSource.html
+    pub fn command_source(&self) -> Result<CommandSource, Error> {
+        #[cfg(unix)]
+        let (program, args) = self.transport.wrap(&self.command, &self.args);
+        #[cfg(not(unix))]
+        let (program, args) = (self.command.clone(), self.args.clone());
+        let spawn_error = |source| Error::Spawn {
+            command: program.to_string_lossy().into_owned(),
+            source,
+        };
+        #[cfg(unix)]
+        let pty = match self.pty {
+            Some(size) => Some(pty::open(size).map_err(spawn_error)?),
+            None => None,
+        };
+        let mut command = Command::new(&program);
+        command
+            .args(&args)
+            .envs(self.envs.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::null())
+            .stderr(if self.capture_stderr {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            });
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        let waker = Waker::new().map_err(spawn_error)?;
+        #[cfg(unix)]
+        if let Some((reader, terminal)) = pty {
+            command.stdout(Stdio::from(terminal));
+            let child = command.spawn().map_err(spawn_error)?;
+            // the terminal only ends once the command closed it,
+            // not while this process keeps it open too
+            drop(command);
+            let source = CommandSource::on_pty(child, reader, waker);
+            return Ok(source.over(self.transport.clone()));
+        }
+        command.stdout(Stdio::piped());
+        let child = command.spawn().map_err(spawn_error)?;
+        let source = CommandSource::new(child, waker);
+        #[cfg(unix)]
+        let source = source.over(self.transport.clone());
+        Ok(source)
+    }
+    /// A channel bounded by the configured capacity, if any
+    pub(crate) fn event_channel<T>(&self) -> (EventSender<T>, Receiver<T>) {
+        match self.channel_capacity {
+            Some(capacity) => {
+                let (sender, events) = sync_channel(capacity);
+                (EventSender::Bounded(sender), events)
+            }
+            None => {
+                let (sender, events) = channel();
+                (EventSender::Unbounded(sender), events)
+            }
+        }
+    }
+    /// The host label given to events, if any
+    pub(crate) fn label(&self) -> Option<&str> {
+        #[cfg(unix)]
+        let transport = self.transport.host_label();
+        #[cfg(not(unix))]
+        let transport = None;
+        self.host_label.as_deref().or(transport)
+    }
+    /// The event labeled with the host, if any
+    pub(crate) fn labeled(&self, mut event: Event) -> Event {
+        if let Some(host) = self.label() {
+            event.set_host(host);
+        }
+        event
+    }
+    /// Starts the thread that parses the lines of the source and applies
+    /// actions, along with a reader that blocks on the source and a
+    /// forwarder that blocks on the actions, so that neither waits for
+    /// the other
+    pub(crate) fn spawn_source<S: EventSource>(
+        &self,
+        mut source: S,
+        action: Receiver<Action>,
+    ) -> MonitorParts {
+        let (sender, events) = self.event_channel();
+        // the reader reads ahead by one line at most, so a bounded
+        // channel still stops it from reading
+        let (inbox, input) = sync_channel(0);
+        let stopping = Arc::new(AtomicBool::new(false));
+        let pid = source.pid();
+        let interrupter = source.interrupter();
+
+        let reader_inbox = inbox.clone();
+        let reader_stopping = Arc::clone(&stopping);
+        thread::spawn(move || read_lines(LineReader::new(source), &reader_inbox, &reader_stopping));
+        thread::spawn(move || forward_actions(action, &inbox));
+
+        let config = self.clone();
+        let thread = thread::spawn(move || {
+            let result = config.run(pid, &input, &sender, &stopping, interrupter);
+            if let Err(e) = &result {
+                sender.send(Err(e.duplicate()));
+            }
+            result
+        });
+
+        MonitorParts {
+            thread,
+            events,
+            pid,
+            stderr: StderrTail::default(),
+        }
+    }
+    /// Parses lines and applies actions in the order they arrive, until
+    /// the source ends, returning why, or until it is stopped
+    fn run(
+        &self,
+        pid: Option<u32>,
+        input: &Receiver<Input>,
+        sender: &EventSender,
+        stopping: &AtomicBool,
+        interrupter: Option<Interrupter>,
+    ) -> Result<(), Error> {
+        let mut control = Control::new(pid);
+        loop {
+            let listening = match input.recv() {
+                Ok(Input::Line(line)) => {
+                    let item = parse_bytes(line, &self.options).map(|event| self.labeled(event));
+                    sender.send_all(control.admit(item))
+                }
+                Ok(Input::Action(action)) => match control.apply(action) {
+                    Applied::Stop => false,
+                    Applied::Deliver(items) => sender.send_all(items),
+                },
+                // end of the source, e.g. the command exited or closed stdout
+                Ok(Input::Ended(result)) => {
+                    sender.send_all(control.take_buffer());
+                    return result;
+                }
+                Err(_) => return Ok(()),
+            };
+            if !listening {
+                return stop_reader(input, stopping, interrupter);
+            }
+        }
+    }
+}
+
+/// What the monitor thread waits for
+enum Input {
+    Line(Vec<u8>),
+    Action(Action),
+    /// The source ended or failed, and was released
+    Ended(Result<(), Error>),
+}
+
+/// Reads lines until the source ends or the monitor stops, then sends
+/// why
+fn read_lines<S: EventSource>(
+    mut lines: LineReader<S>,
+    inbox: &SyncSender<Input>,
+    stopping: &AtomicBool,
+) {
+    let result = read_until_end(&mut lines, inbox, stopping);
+    if result.is_err() {
+        // do not leave the source running after a failure
+        let _ = lines.source_mut().stop();
+    }
+    let _ = inbox.send(Input::Ended(result));
+}
+
+fn read_until_end<S: EventSource>(
+    lines: &mut LineReader<S>,
+    inbox: &SyncSender<Input>,
+    stopping: &AtomicBool,
+) -> Result<(), Error> {
+    let mut ended = false;
+    loop {
+        if stopping.load(Ordering::SeqCst) {
+            return lines.source_mut().stop();
+        }
+        match lines.next_line().map_err(Error::ReadFailed)? {
+            Frame::End => return lines.source_mut().finish(),
+            Frame::Line(line) => {
+                if inbox.send(Input::Line(line)).is_err() {
+                    return lines.source_mut().stop();
+                }
+            }
+            // interrupted, while something else keeps the source open
+            Frame::TimedOut if ended => return lines.source_mut().finish(),
+            // interrupted by a stop, or when the command exited
+            Frame::TimedOut => {}
+        }
+        if !ended && lines.source_mut().has_ended()? {
+            // read what is left before reporting the end
+            ended = true;
+        }
+    }
+}
+
+/// Hands the actions over to the monitor thread as they are sent
+fn forward_actions(action: Receiver<Action>, inbox: &SyncSender<Input>) {
+    for next in action {
+        if inbox.send(Input::Action(next)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Asks the reader to release the source, waiting until it did if its
+/// read can be interrupted. Otherwise the reader releases the source
+/// once its read returns, without anyone waiting for it.
+fn stop_reader(
+    input: &Receiver<Input>,
+    stopping: &AtomicBool,
+    interrupter: Option<Interrupter>,
+) -> Result<(), Error> {
+    stopping.store(true, Ordering::SeqCst);
+    match interrupter {
+        Some(interrupt) => interrupt(),
+        None => return Ok(()),
+    }
+    loop {
+        match input.recv() {
+            Ok(Input::Ended(result)) => return result,
+            Ok(_) => {}
+            Err(_) => return Ok(()),
+        }
+    }
+}
+
+/// What [`MonitorBuilder::spawn`] starts
+pub(crate) struct MonitorParts {
+    pub thread: thread::JoinHandle<Result<(), Error>>,
+    pub events: Receiver<Result<Event, Error>>,
+    pub pid: Option<u32>,
+    pub stderr: StderrTail,
+}
+
+/// Either end of a bounded or an unbounded channel
+pub(crate) enum EventSender<T = Result<Event, Error>> {
+    Unbounded(Sender<T>),
+    Bounded(SyncSender<T>),
+}
+
+impl<T> EventSender<T> {
+    /// Sends the item, returning whether the receiver is still there
+    pub(crate) fn send(&self, item: T) -> bool {
+        match self {
+            EventSender::Unbounded(sender) => sender.send(item).is_ok(),
+            EventSender::Bounded(sender) => sender.send(item).is_ok(),
+        }
+    }
+    /// Sends the items in order, returning whether the receiver is still there
+    fn send_all<I: IntoIterator<Item = T>>(&self, items: I) -> bool {
+        items.into_iter().all(|item| self.send(item))
+    }
+}
+
+/// The last lines a command printed to stderr
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StderrTail {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl StderrTail {
+    /// Keeps the given line, forgetting the oldest one if full
+    pub(crate) fn push(&self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line).trim_end().to_string();
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() == STDERR_TAIL_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+    /// The kept lines, oldest first
+    pub(crate) fn lines(&self) -> Vec<String> {
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        lines.iter().cloned().collect()
+    }
+    /// Reads the given stderr pipe until it closes
+    pub(crate) fn read_from<R: Read>(self, pipe: R) {
+        for line in BufReader::new(pipe).split(b'\n') {
+            match line {
+                Ok(line) => self.push(&line),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+/// A running `diskutil activity` whose events can be iterated.
+///
+/// The subprocess is killed and reaped when the monitor is stopped or
+/// dropped, so breaking out of a loop is enough to clean up.
+///
+/// # Example:
+///
+/// ```no_run
+/// use mac_disk_monitor::Monitor;
+///
+/// let monitor = Monitor::new().unwrap();
+/// for event in monitor {
+///     match event {
+///         Ok(event) => println!("{}", event.to_json()),
+///         Err(e) => eprintln!("Error: {}", e),
+///     }
+/// }
+/// ```
+pub struct Monitor {
+    actions: Sender<Action>,
+    events: Receiver<Result<Event, Error>>,
+    thread: Option<thread::JoinHandle<Result<(), Error>>>,
+    pid: Option<u32>,
+    stderr: StderrTail,
+}
+
+impl Monitor {
+    /// Runs `diskutil activity`
+    pub fn new() -> Result<Monitor, Error> {
+        MonitorBuilder::new().build()
+    }
+    /// Runs the given command instead of `diskutil activity`
+    pub fn with_command(command: &str, args: Vec<&str>) -> Result<Monitor, Error> {
+        MonitorBuilder::new().command(command).args(args).build()
+    }
+    /// Runs the given command and parses its lines with the given [`ParseOptions`]
+    ///
+    /// [`ParseOptions`]: ./struct.ParseOptions.html
+    pub fn with_options(
+        command: &str,
+        args: Vec<&str>,
+        options: ParseOptions,
+    ) -> Result<Monitor, Error> {
+        MonitorBuilder::new()
+            .command(command)
+            .args(args)
+            .parse_options(options)
+            .build()
+    }
+    /// Monitors the given source instead of a command, see [`EventSource`]
+    ///
+    /// [`EventSource`]: ./source/trait.EventSource.html
+    pub fn from_source<S: EventSource>(source: S) -> Monitor {
+        MonitorBuilder::new().build_from_source(source)
+    }
+    fn from_parts(actions: Sender<Action>, parts: MonitorParts) -> Monitor {
+        Monitor {
+            actions,
+            events: parts.events,
+            thread: Some(parts.thread),
+            pid: parts.pid,
+            stderr: parts.stderr,
+        }
+    }
+    /// The process id of the command, `None` for other sources
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+    /// The last lines the command printed to stderr, oldest first,
+    /// when built with [`MonitorBuilder::capture_stderr()`]
+    ///
+    /// [`MonitorBuilder::capture_stderr()`]: ./struct.MonitorBuilder.html#method.capture_stderr
+    pub fn stderr_tail(&self) -> Vec<String> {
+        self.stderr.lines()
+    }
+    /// A sender of [`Action`]s to the monitor, e.g. to stop it from a
+    /// signal handler while another thread is iterating
+    ///
+    /// [`Action`]: ./enum.Action.html
+    pub fn actions(&self) -> Sender<Action> {
+        self.actions.clone()
+    }
+    /// Keeps the command running but holds events back, see [`PausePolicy`]
+    ///
+    /// [`PausePolicy`]: ./enum.PausePolicy.html
+    pub fn pause(&self, policy: PausePolicy) {
+        let _ = self.actions.send(Action::Pause(policy));
+    }
+    /// Delivers the events held back and forwards new ones again
+    pub fn resume(&self) {
+        let _ = self.actions.send(Action::Resume);
+    }
+    /// Delivers the events held back while staying paused
+    pub fn flush(&self) {
+        let _ = self.actions.send(Action::Flush);
+    }
+    /// Changes which events are forwarded, without restarting the command
+    pub fn set_filter(&self, filter: EventFilter) {
+        let _ = self.actions.send(Action::SetFilter(filter));
+    }
+    /// Asks the thread for its [`MonitorStatus`], waiting up to `timeout`
+    /// for the answer. `None` if the thread has finished or is busy.
+    ///
+    /// [`MonitorStatus`]: ./struct.MonitorStatus.html
+    pub fn status(&self, timeout: Duration) -> Option<MonitorStatus> {
+        let (reply, status) = channel();
+        self.actions.send(Action::Status(reply)).ok()?;
+        status.recv_timeout(timeout).ok()
+    }
+    /// Waits up to `timeout` for the next event.
+    ///
+    /// Returns `None` if no event arrived in time or if the monitor has
+    /// finished, which [`Monitor::is_finished()`] tells apart.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<Event, Error>> {
+        match self.events.recv_timeout(timeout) {
+            Ok(item) => Some(item),
+            Err(RecvTimeoutError::Disconnected) => self.finish(),
+            Err(RecvTimeoutError::Timeout) => None,
+        }
+    }
+    /// Returns the next event if one is already available, without waiting
+    pub fn try_next(&mut self) -> Option<Result<Event, Error>> {
+        match self.events.try_recv() {
+            Ok(item) => Some(item),
+            Err(TryRecvError::Disconnected) => self.finish(),
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+    /// Whether the command has exited and every event was consumed
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_none()
+    }
+    /// Kills and reaps the command, waiting for the thread to stop.
+    /// Sources without [`EventSource::interrupter()`] are released once
+    /// their pending read returns, without waiting for it.
+    ///
+    /// [`EventSource::interrupter()`]: ./source/trait.EventSource.html#method.interrupter
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.shutdown()
+    }
+    fn shutdown(&mut self) -> Result<(), Error> {
+        let _ = self.actions.send(Action::Stop);
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(()),
+        };
+        // unblock the reader in case it waits for room in a bounded channel
+        while !thread.is_finished() {
+            let received = self.events.recv_timeout(Duration::from_millis(10));
+            if let Err(RecvTimeoutError::Disconnected) = received {
+                break;
+            }
+        }
+        thread
+            .join()
+            .unwrap_or_else(|payload| Err(Error::panicked(payload)))
+    }
+    /// Joins the thread once the channel is closed. Its error, if any,
+    /// was already received through the channel, unless it panicked.
+    fn finish(&mut self) -> Option<Result<Event, Error>> {
+        match self.thread.take()?.join() {
+            Ok(_) => None,
+            Err(payload) => Some(Err(Error::panicked(payload))),
+        }
+    }
+}
+
+impl Iterator for Monitor {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Result<Event, Error>> {
+        match self.events.recv() {
+            Ok(item) => Some(item),
+            Err(_) => self.finish(),
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
@@ -0,0 +1,370 @@
+//! Grammar for the lines printed by `diskutil activity`:
+//!
+//! ```text
+//! line      = "***" name ws* "(" disk-ref { ws* "," ws* attribute } ws* ")" [ ws* "Comment=" comment ] ws* "Time=" time
+//! name      = ( ALPHA | DIGIT | "_" )+
+//! disk-ref  = "'" bsd-name "'" | "(no BSD name)" | "no DADiskRef"
+//! attribute = name ws* "=" ws* quoted
+//! quoted    = "'" { any character } "'"
+//! comment   = any text up to the last " Time="
+//! time      = any text up to the next whitespace
+//! ```
+//!
+//! Any text after the `Time=` value is not part of the grammar and is
+//! kept in [`RawLine::trailing`].
+//!
+//! `diskutil` does not escape the values it prints, so a quote only
+//! closes a `quoted` value when what follows it is valid according to
+//! the grammar: either `, Key = '` or `)` followed by `Comment=`,
+//! `Time=` or the end of the line. This allows volume names like
+//! `Bob's Drive` or `Backup (old), 2021` to be parsed verbatim.
+use crate::decoding::Decoding;
+use crate::time::{AmbiguousTime, EventTimeZone};
+use std::borrow::Cow;
+use std::fmt;
+
+/// Options controlling how lines are turned into [`Event`]s.
+///
+/// [`Event`]: ./struct.Event.html
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// The time zone in which `Time=` values are interpreted
+    pub timezone: EventTimeZone,
+    /// How `Time=` values repeated by a daylight saving time change are resolved
+    pub ambiguous_time: AmbiguousTime,
+    /// Whether to keep the original line and any text that is not part
+    /// of the grammar in the [`Event`]
+    ///
+    /// [`Event`]: ./struct.Event.html
+    pub keep_raw: bool,
+    /// How lines read from a command or another source are decoded
+    pub decoding: Decoding,
+}
+
+/// The part of a `diskutil activity` line that could not be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParseErrorKind {
+    /// The line does not start with `***`
+    Prefix,
+    /// The event name right after `***` is missing or invalid
+    EventName,
+    /// The disk reference, e.g. `'disk3s1'`, `(no BSD name)` or `no DADiskRef`
+    DiskRef,
+    /// The `Key = 'value'` pairs that follow the disk reference
+    KeyValueList,
+    /// The `Comment=` field
+    Comment,
+    /// The `Time=` field
+    Timestamp,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseErrorKind::Prefix => "prefix",
+            ParseErrorKind::EventName => "event name",
+            ParseErrorKind::DiskRef => "disk reference",
+            ParseErrorKind::KeyValueList => "key/value list",
+            ParseErrorKind::Comment => "comment",
+            ParseErrorKind::Timestamp => "timestamp",
+        })
+    }
+}
+
+/// The error returned when a line cannot be parsed into an [`Event`].
+///
+/// Carries which part of the line failed and the byte offset where
+/// the parser gave up.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::{Event, ParseErrorKind};
+///
+/// let error = Event::parse("***DiskPeek ('disk3s1') Time=yesterday").unwrap_err();
+/// assert_eq!(error.kind(), ParseErrorKind::Timestamp);
+/// assert_eq!(error.offset(), 29);
+/// ```
+///
+/// [`Event`]: ./struct.Event.html
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid {kind} at byte {offset}: {message}")]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    offset: usize,
+    message: String,
+}
+
+impl ParseError {
+    /// Creates a new ParseError
+    pub fn new(kind: ParseErrorKind, offset: usize, message: &str) -> ParseError {
+        ParseError {
+            kind,
+            offset,
+            message: String::from(message),
+        }
+    }
+    /// The part of the line that failed to parse
+    pub fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+    /// The byte offset within the line where parsing failed
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    /// A human-readable description of what was expected
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+    pub(crate) fn shifted(mut self, by: usize) -> ParseError {
+        self.offset += by;
+        self
+    }
+}
+
+/// How the disk is referenced in a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiskRefToken<'a> {
+    /// A quoted BSD name such as `'disk3s1'`
+    Bsd(Cow<'a, str>),
+    /// `(no BSD name)`
+    NoBsdName,
+    /// `no DADiskRef`
+    NoDiskRef,
+}
+
+/// The pieces of a `diskutil activity` line, borrowed from the line itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawLine<'a> {
+    pub name: &'a str,
+    pub disk_ref: DiskRefToken<'a>,
+    /// The byte offset of the disk reference within the line
+    pub disk_ref_offset: usize,
+    pub attributes: Vec<(&'a str, Cow<'a, str>)>,
+    pub comment: Option<&'a str>,
+    pub time: &'a str,
+    /// The byte offset of the `Time=` value within the line
+    pub time_offset: usize,
+    /// Text after the `Time=` value, without surrounding whitespace
+    pub trailing: Option<&'a str>,
+}
+
+impl<'a> RawLine<'a> {
+    /// The value of the first attribute with the given key, if any
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.as_ref())
+    }
+}
+
+/// Splits a line printed by `diskutil activity` into its parts
+/// without interpreting their values.
+///
+/// See the [module documentation](./index.html) for the grammar.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::parser::{parse_line, DiskRefToken};
+///
+/// let line = parse_line("***DiskAppeared ('disk5s1', DAVolumeName = 'Backup (old), 2021') Time=20220108-20:22:05.1454").unwrap();
+/// assert_eq!(line.name, "DiskAppeared");
+/// assert_eq!(line.disk_ref, DiskRefToken::Bsd("disk5s1".into()));
+/// assert_eq!(line.attribute("DAVolumeName"), Some("Backup (old), 2021"));
+/// ```
+pub fn parse_line(line: &str) -> Result<RawLine<'_>, ParseError> {
+    let mut cursor = Cursor::new(line);
+
+    if !cursor.eat("***") {
+        return Err(cursor.error(ParseErrorKind::Prefix, "expected \"***\""));
+    }
+    let name = cursor.take_while(is_name_char);
+    if name.is_empty() {
+        return Err(cursor.error(ParseErrorKind::EventName, "expected an event name"));
+    }
+    cursor.skip_whitespace();
+    if !cursor.eat("(") {
+        return Err(cursor.error(ParseErrorKind::DiskRef, "expected \"(\""));
+    }
+    let disk_ref_offset = cursor.pos;
+    let disk_ref = if cursor.eat("'") {
+        // BSD names never contain quotes, so the first one closes it
+        let bsd_name = cursor.take_while(|c| c != '\'');
+        if !cursor.eat("'") {
+            return Err(cursor.error(ParseErrorKind::DiskRef, "unterminated BSD name"));
+        }
+        DiskRefToken::Bsd(Cow::Borrowed(bsd_name))
+    } else if cursor.eat("(no BSD name)") {
+        DiskRefToken::NoBsdName
+    } else if cursor.eat("no DADiskRef") {
+        DiskRefToken::NoDiskRef
+    } else {
+        return Err(cursor.error(ParseErrorKind::DiskRef, "expected a disk reference"));
+    };
+
+    let mut attributes = Vec::new();
+    loop {
+        cursor.skip_whitespace();
+        if cursor.eat(")") {
+            break;
+        }
+        if !cursor.eat(",") {
+            return Err(cursor.error(ParseErrorKind::KeyValueList, "expected \",\" or \")\""));
+        }
+        cursor.skip_whitespace();
+        let key = cursor.take_while(is_name_char);
+        if key.is_empty() {
+            return Err(cursor.error(ParseErrorKind::KeyValueList, "expected a key"));
+        }
+        cursor.skip_whitespace();
+        if !cursor.eat("=") {
+            return Err(cursor.error(ParseErrorKind::KeyValueList, "expected \"=\""));
+        }
+        cursor.skip_whitespace();
+        if cursor.peek() != Some('\'') {
+            return Err(cursor.error(ParseErrorKind::KeyValueList, "expected a quoted value"));
+        }
+        match cursor.quoted() {
+            Some(value) => attributes.push((key, Cow::Borrowed(value))),
+            None => {
+                return Err(cursor.error(ParseErrorKind::KeyValueList, "unterminated value"));
+            }
+        }
+    }
+
+    let (comment, time, time_offset) = parse_tail(&mut cursor)?;
+    let trailing = Some(cursor.rest().trim()).filter(|rest| !rest.is_empty());
+
+    Ok(RawLine {
+        name,
+        disk_ref,
+        disk_ref_offset,
+        attributes,
+        comment,
+        time,
+        time_offset,
+        trailing,
+    })
+}
+
+/// Parses what follows the closing parenthesis: `Comment=... Time=...`
+fn parse_tail<'a>(
+    cursor: &mut Cursor<'a>,
+) -> Result<(Option<&'a str>, &'a str, usize), ParseError> {
+    cursor.skip_whitespace();
+    let comment = if cursor.eat("Comment=") {
+        let rest = cursor.rest();
+        let end = match rest.rfind(" Time=") {
+            Some(end) => end,
+            None => {
+                return Err(cursor.error(
+                    ParseErrorKind::Comment,
+                    "expected \"Time=\" after the comment",
+                ))
+            }
+        };
+        if rest[..end].trim().is_empty() {
+            return Err(cursor.error(ParseErrorKind::Comment, "empty comment"));
+        }
+        cursor.advance(end);
+        cursor.skip_whitespace();
+        Some(rest[..end].trim_end())
+    } else {
+        None
+    };
+
+    if !cursor.eat("Time=") {
+        return Err(cursor.error(ParseErrorKind::Timestamp, "expected \"Time=\""));
+    }
+    let time_offset = cursor.pos;
+    let time = cursor.take_while(|c| !c.is_whitespace());
+    if time.is_empty() {
+        return Err(cursor.error(ParseErrorKind::Timestamp, "empty timestamp"));
+    }
+    Ok((comment, time, time_offset))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Whether a quote right before `rest` can close a quoted value:
+/// it must be followed by either `, Key =` or by `)` and a valid tail.
+fn closes_quoted(rest: &str) -> bool {
+    let mut cursor = Cursor::new(rest);
+    cursor.skip_whitespace();
+    if cursor.eat(",") {
+        cursor.skip_whitespace();
+        if cursor.take_while(is_name_char).is_empty() {
+            return false;
+        }
+        cursor.skip_whitespace();
+        cursor.eat("=")
+    } else if cursor.eat(")") {
+        parse_tail(&mut cursor).is_ok()
+    } else {
+        false
+    }
+}
+
+struct Cursor<'a> {
+    line: &'a str,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(line: &'a str) -> Cursor<'a> {
+        Cursor { line, pos: 0 }
+    }
+    fn rest(&self) -> &'a str {
+        &self.line[self.pos..]
+    }
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+    fn advance(&mut self, by: usize) {
+        self.pos += by;
+    }
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.advance(token.len());
+            true
+        } else {
+            false
+        }
+    }
+    fn take_while<F: Fn(char) -> bool>(&mut self, predicate: F) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find(|c| !predicate(c)).unwrap_or(rest.len());
+        self.advance(end);
+        &rest[..end]
+    }
+    fn skip_whitespace(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+    /// Consumes a quoted value, the cursor must be at the opening quote.
+    ///
+    /// The value is read verbatim, backslashes included, since `diskutil`
+    /// never escapes anything. When no quote is followed by valid syntax
+    /// the first one closes the value, so that the error is reported
+    /// where the line actually goes wrong.
+    fn quoted(&mut self) -> Option<&'a str> {
+        let rest = &self.rest()[1..];
+        let end = scan_quoted(rest).or_else(|| rest.find('\''))?;
+        self.advance(1 + end + 1);
+        Some(&rest[..end])
+    }
+    fn error(&self, kind: ParseErrorKind, message: &str) -> ParseError {
+        ParseError::new(kind, self.pos, message)
+    }
+}
+
+/// Finds the closing quote of a value that starts right after an opening
+/// quote, returning its byte offset
+fn scan_quoted(rest: &str) -> Option<usize> {
+    rest.char_indices()
+        .find(|&(index, c)| c == '\'' && closes_quoted(&rest[index + 1..]))
+        .map(|(index, _)| index)
+}
@@ -0,0 +1,249 @@
+//! Running a command on a pseudo-terminal.
+//!
+//! Many programs, and the C library under them, block-buffer their
+//! stdout when it is a pipe and only line-buffer it when it is a
+//! terminal. On a pseudo-terminal each event arrives as soon as it is
+//! printed, at the cost of the terminal control sequences a program may
+//! print when it believes to talk to a user, which are stripped before
+//! parsing.
+use crate::wake::set_cloexec;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+/// The size of the pseudo-terminal a command runs on, see
+/// [`MonitorBuilder::pty()`].
+///
+/// Wide by default, so that programs that fit their output to the
+/// terminal do not truncate the lines of events.
+///
+/// [`MonitorBuilder::pty()`]: ../struct.MonitorBuilder.html#method.pty
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WindowSize {
+    pub rows: u16,
+    pub columns: u16,
+}
+
+impl Default for WindowSize {
+    fn default() -> WindowSize {
+        WindowSize {
+            rows: 50,
+            columns: 512,
+        }
+    }
+}
+
+impl WindowSize {
+    /// A window of the given amount of rows and columns
+    pub fn new(rows: u16, columns: u16) -> WindowSize {
+        WindowSize { rows, columns }
+    }
+}
+
+/// Opens a pseudo-terminal of the given size, returning the end read by
+/// the monitor and the end given to the command
+pub(crate) fn open(size: WindowSize) -> io::Result<(PtyReader, OwnedFd)> {
+    let mut master: libc::c_int = -1;
+    let mut slave: libc::c_int = -1;
+    let mut window = libc::winsize {
+        ws_row: size.rows,
+        ws_col: size.columns,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let opened = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            // `*mut` on macOS, `*const` elsewhere
+            std::ptr::addr_of_mut!(window),
+        )
+    };
+    if opened != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // owned right away, so that both are closed on error
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+    // neither end may leak into other commands
+    set_cloexec(master.as_raw_fd())?;
+    set_cloexec(slave.as_raw_fd())?;
+    Ok((PtyReader(File::from(master)), slave))
+}
+
+/// The end of a pseudo-terminal read by the monitor
+#[derive(Debug)]
+pub(crate) struct PtyReader(File);
+
+impl Read for PtyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            // Linux reports EIO once every process closed the terminal
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+impl AsRawFd for PtyReader {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// Where a [`ControlSequenceFilter`] is within a control sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Text,
+    /// After `ESC`
+    Escape,
+    /// After `ESC` and intermediate bytes, until a final byte
+    EscapeIntermediate,
+    /// After `ESC [`, until a final byte
+    Csi,
+    /// After `ESC ]`, `ESC P`, `ESC X`, `ESC ^` or `ESC _`, until `BEL`
+    /// or `ESC \`
+    String,
+    /// After `ESC` within a string
+    StringEscape,
+}
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+/// Strips terminal control sequences, such as colors and cursor
+/// movements, and control characters other than tabs and line
+/// terminators from what it reads. Sequences split across reads are
+/// stripped as well.
+#[derive(Debug)]
+pub(crate) struct ControlSequenceFilter<R> {
+    inner: R,
+    state: State,
+}
+
+impl<R: Read> ControlSequenceFilter<R> {
+    pub(crate) fn new(inner: R) -> ControlSequenceFilter<R> {
+        ControlSequenceFilter {
+            inner,
+            state: State::Text,
+        }
+    }
+    /// Moves the bytes to keep to the start of `buf`, returning how many
+    fn filter(&mut self, buf: &mut [u8]) -> usize {
+        let mut kept = 0;
+        for index in 0..buf.len() {
+            let byte = buf[index];
+            self.state = match (self.state, byte) {
+                (State::Text, ESC) => State::Escape,
+                (State::Text, b'\t' | b'\n' | b'\r') | (State::Text, 0x20..=0x7e | 0x80..=0xff) => {
+                    buf[kept] = byte;
+                    kept += 1;
+                    State::Text
+                }
+                (State::Text, _) => State::Text,
+                (State::Escape, b'[') => State::Csi,
+                (State::Escape, b']' | b'P' | b'X' | b'^' | b'_') => State::String,
+                (State::Escape | State::EscapeIntermediate, 0x20..=0x2f) => {
+                    State::EscapeIntermediate
+                }
+                (State::Escape | State::EscapeIntermediate, _) => State::Text,
+                (State::Csi, 0x40..=0x7e) => State::Text,
+                (State::Csi, _) => State::Csi,
+                (State::String, BEL) => State::Text,
+                (State::String, ESC) => State::StringEscape,
+                (State::String, _) => State::String,
+                (State::StringEscape, b'\\') => State::Text,
+                (State::StringEscape, _) => State::String,
+            };
+        }
+        kept
+    }
+}
+
+impl<R: Read> Read for ControlSequenceFilter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.inner.read(buf)?;
+            if read == 0 {
+                return Ok(0);
+            }
+            // a read of control sequences only is not the end
+            let kept = self.filter(&mut buf[..read]);
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+/// The given bytes without terminal control sequences, as stripped from
+/// the output of a command run on a pseudo-terminal.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::strip_control_sequences;
+///
+/// let line = b"\x1b[1m***DiskPeek\x1b[0m ('disk4') Time=20220108-20:22:35.8607\r\n";
+/// assert_eq!(
+///     strip_control_sequences(line),
+///     b"***DiskPeek ('disk4') Time=20220108-20:22:35.8607\r\n"
+/// );
+/// ```
+pub fn strip_control_sequences(bytes: &[u8]) -> Vec<u8> {
+    let mut stripped = bytes.to_vec();
+    let kept = ControlSequenceFilter::new(io::empty()).filter(&mut stripped);
+    stripped.truncate(kept);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{strip_control_sequences, ControlSequenceFilter};
+    use k9::assert_equal;
+    use std::io::{self, Read};
+
+    /// Hands out one byte per read
+    struct Bytes(Vec<u8>, usize);
+
+    impl Read for Bytes {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.get(self.1) {
+                Some(byte) => {
+                    buf[0] = *byte;
+                    self.1 += 1;
+                    Ok(1)
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    fn stripped(bytes: &[u8]) -> String {
+        String::from_utf8(strip_control_sequences(bytes)).unwrap()
+    }
+
+    #[test]
+    fn test_strips_csi_and_osc_sequences() {
+        assert_equal!(stripped(b"\x1b[38;5;196mred\x1b[0m"), "red");
+        assert_equal!(stripped(b"\x1b]0;title\x07a\x1b]2;x\x1b\\b"), "ab");
+        assert_equal!(stripped(b"\x1b(Ba\x1b=b\x1b[?25l"), "ab");
+    }
+    #[test]
+    fn test_keeps_text_and_line_terminators() {
+        assert_equal!(stripped(b"a\tb\r\n\x08\x00c\x7f"), "a\tb\r\nc");
+        assert_equal!(
+            stripped("Time Machine – é\n".as_bytes()),
+            "Time Machine – é\n"
+        );
+    }
+    #[test]
+    fn test_sequences_split_across_reads() {
+        let mut filter =
+            ControlSequenceFilter::new(Bytes(b"\x1b[1ma\x1b]0;t\x1b\\b\n".to_vec(), 0));
+        let mut text = String::new();
+        filter.read_to_string(&mut text).unwrap();
+        assert_equal!(text, "ab\n");
+    }
+}
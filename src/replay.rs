@@ -0,0 +1,230 @@
+use crate::parser::parse_line;
+use crate::source::EventSource;
+use crate::std::Error;
+use crate::time::parse_apple_time;
+use chrono::NaiveDateTime;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, ErrorKind};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The longest a single read sleeps, so that actions are handled while
+/// waiting for a late line
+const SLEEP_SLICE: Duration = Duration::from_millis(100);
+
+/// Plays back a recorded `diskutil activity` log as an [`EventSource`].
+///
+/// Each line is played back as long after the first one as recorded in
+/// their `Time=` values, divided by the speed. Lines without a valid `Time=`, such as
+/// the `***Begin monitoring` banner, are played back immediately.
+///
+/// # Example:
+///
+/// ```no_run
+/// use mac_disk_monitor::{parse_apple_time, Monitor, ReplaySource};
+///
+/// let source = ReplaySource::open("incident.log")
+///     .unwrap()
+///     .speed(10.0)
+///     .start(parse_apple_time("20220108-20:22:05").unwrap());
+/// for event in Monitor::from_source(source) {
+///     println!("{}", event.unwrap().to_json());
+/// }
+/// ```
+///
+/// [`EventSource`]: ./source/trait.EventSource.html
+pub struct ReplaySource<R> {
+    reader: R,
+    speed: Option<f64>,
+    start: Option<NaiveDateTime>,
+    end: Option<NaiveDateTime>,
+    /// The recorded time of the first line played back, and when it was
+    anchor: Option<(NaiveDateTime, Instant)>,
+    /// A line that was read but is not due yet, and when it is due
+    pending: Option<(Vec<u8>, Instant)>,
+    /// Whether the lines read are after the start bound
+    started: bool,
+    ended: bool,
+}
+
+impl ReplaySource<BufReader<File>> {
+    /// Replays the log file at the given path
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ReplaySource<BufReader<File>>, Error> {
+        Ok(ReplaySource::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead + Send + 'static> ReplaySource<R> {
+    /// Replays the lines of the given reader in real time
+    pub fn new(reader: R) -> ReplaySource<R> {
+        ReplaySource {
+            reader,
+            speed: Some(1.0),
+            start: None,
+            end: None,
+            anchor: None,
+            pending: None,
+            started: true,
+            ended: false,
+        }
+    }
+    /// Plays back `speed` times faster than recorded, e.g. `0.5` for
+    /// half speed. Non-positive speeds are the same as [`as_fast_as_possible()`].
+    ///
+    /// [`as_fast_as_possible()`]: #method.as_fast_as_possible
+    pub fn speed(mut self, speed: f64) -> ReplaySource<R> {
+        self.speed = if speed > 0.0 { Some(speed) } else { None };
+        self
+    }
+    /// Plays back every line without waiting
+    pub fn as_fast_as_possible(mut self) -> ReplaySource<R> {
+        self.speed = None;
+        self
+    }
+    /// Skips the lines recorded before the given time
+    pub fn start(mut self, time: NaiveDateTime) -> ReplaySource<R> {
+        self.start = Some(time);
+        self.started = false;
+        self
+    }
+    /// Ends the replay at the first line recorded after the given time
+    pub fn end(mut self, time: NaiveDateTime) -> ReplaySource<R> {
+        self.end = Some(time);
+        self
+    }
+    /// Reads the next line within the time bounds, along with when it
+    /// is due
+    fn next_line(&mut self) -> io::Result<Option<(Vec<u8>, Instant)>> {
+        loop {
+            let mut line = Vec::new();
+            if self.ended || self.reader.read_until(b'\n', &mut line)? == 0 {
+                return Ok(None);
+            }
+            let time = recorded_time(&line);
+            if let Some(time) = time {
+                if self.end.is_some_and(|end| time > end) {
+                    self.ended = true;
+                    return Ok(None);
+                }
+                if !self.started && self.start.is_some_and(|start| time >= start) {
+                    self.started = true;
+                }
+            }
+            if !self.started {
+                continue;
+            }
+            // relative to the first line so that lateness does not add up
+            let due = match (time, self.anchor, self.speed) {
+                (Some(time), Some((first, played)), Some(speed)) => {
+                    let recorded = (time - first).to_std().unwrap_or_default();
+                    played + recorded.div_f64(speed)
+                }
+                (Some(time), None, _) => {
+                    let now = Instant::now();
+                    self.anchor = Some((time, now));
+                    now
+                }
+                _ => Instant::now(),
+            };
+            return Ok(Some((line, due)));
+        }
+    }
+}
+
+impl<R: BufRead + Send + 'static> EventSource for ReplaySource<R> {
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize> {
+        let (next, due) = match self.pending.take() {
+            Some(pending) => pending,
+            None => match self.next_line()? {
+                Some(next) => next,
+                None => return Ok(0),
+            },
+        };
+        let remaining = due.saturating_duration_since(Instant::now());
+        if remaining > SLEEP_SLICE {
+            thread::sleep(SLEEP_SLICE);
+            self.pending = Some((next, due));
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "the next line is not due yet",
+            ));
+        }
+        thread::sleep(remaining);
+        line.extend_from_slice(&next);
+        Ok(next.len())
+    }
+}
+
+/// The `Time=` value of the given line, if it is a valid event line
+fn recorded_time(line: &[u8]) -> Option<NaiveDateTime> {
+    // whatever the decoding, the `Time=` value is plain ASCII
+    let line = String::from_utf8_lossy(line);
+    let raw = parse_line(&line).ok()?;
+    parse_apple_time(raw.time).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReplaySource;
+    use crate::source::EventSource;
+    use crate::time::parse_apple_time;
+    use k9::assert_equal;
+    use std::io::Cursor;
+    use std::time::{Duration, Instant};
+
+    const LOG: &str = "***Begin monitoring DiskArbitration activity
+***DiskPeek ('disk1') Time=20220108-20:22:05.0000
+***DiskPeek ('disk2') Time=20220108-20:22:05.2000
+***DiskPeek ('disk3') Time=20220108-20:22:05.4000
+***DiskPeek ('disk4') Time=20220108-20:22:05.6000
+";
+
+    /// The lines played back, skipping the timeouts of pending lines
+    fn play<S: EventSource>(mut source: S) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = Vec::new();
+            match source.read_line(&mut line) {
+                Ok(0) => return lines,
+                Ok(_) => lines.push(String::from_utf8(line).unwrap().trim_end().to_string()),
+                Err(_) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_replay_reproduces_delays_at_the_given_speed() {
+        let started = Instant::now();
+        let lines = play(ReplaySource::new(Cursor::new(LOG)).speed(2.0));
+        let elapsed = started.elapsed();
+
+        assert_equal!(lines.len(), 5);
+        // 600ms recorded at twice the speed
+        assert_equal!(elapsed >= Duration::from_millis(290), true);
+        assert_equal!(elapsed < Duration::from_millis(600), true);
+    }
+    #[test]
+    fn test_replay_as_fast_as_possible() {
+        let started = Instant::now();
+        let lines = play(ReplaySource::new(Cursor::new(LOG)).as_fast_as_possible());
+
+        assert_equal!(lines.len(), 5);
+        assert_equal!(started.elapsed() < Duration::from_millis(100), true);
+    }
+    #[test]
+    fn test_replay_time_bounds() {
+        let source = ReplaySource::new(Cursor::new(LOG))
+            .as_fast_as_possible()
+            .start(parse_apple_time("20220108-20:22:05.1").unwrap())
+            .end(parse_apple_time("20220108-20:22:05.4").unwrap());
+
+        assert_equal!(
+            play(source),
+            vec![
+                "***DiskPeek ('disk2') Time=20220108-20:22:05.2000",
+                "***DiskPeek ('disk3') Time=20220108-20:22:05.4000",
+            ]
+        );
+    }
+}
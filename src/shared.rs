@@ -0,0 +1,333 @@
+use crate::attributes::NULL_VALUE;
+use crate::event::{Event, EventKind};
+use crate::monitor::{Monitor, MonitorBuilder};
+use crate::std::{Action, Error};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// A single command shared by every part of a program that monitors
+/// disks.
+///
+/// The command starts with the first [`Subscription`] and each event is
+/// delivered to every subscription. Once the last one is dropped, the
+/// command is stopped in the background, and started again by the next
+/// subscription.
+///
+/// Since `diskutil activity` starts with a `DiskAppeared` event for each
+/// disk already there, the monitor keeps the disks that appeared and did
+/// not disappear, so that late subscribers can ask for them with
+/// [`SharedMonitor::subscribe_with_inventory()`].
+///
+/// # Example:
+///
+/// ```no_run
+/// use mac_disk_monitor::SharedMonitor;
+/// use std::thread;
+///
+/// let volumes = SharedMonitor::global().subscribe().unwrap();
+/// let indexer = thread::spawn(|| {
+///     // sees the disks that are already there, without a second `diskutil`
+///     for event in SharedMonitor::global().subscribe_with_inventory().unwrap() {
+///         println!("indexer: {}", event.unwrap().to_json());
+///     }
+/// });
+/// for event in volumes {
+///     println!("volumes: {}", event.unwrap().to_json());
+/// }
+/// ```
+///
+/// [`Subscription`]: ./struct.Subscription.html
+/// [`SharedMonitor::subscribe_with_inventory()`]: #method.subscribe_with_inventory
+#[derive(Clone)]
+pub struct SharedMonitor {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    builder: MonitorBuilder,
+    state: Mutex<State>,
+    /// Held while a subscription starts the command, so that it starts
+    /// once without holding the state meanwhile
+    starting: Mutex<()>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Incremented each time the command starts, so that what belongs to
+    /// an earlier command leaves the current one alone
+    generation: u64,
+    next_id: u64,
+    running: Option<Running>,
+}
+
+/// The command that currently runs and who listens to it
+struct Running {
+    pid: Option<u32>,
+    actions: Sender<Action>,
+    subscribers: Vec<(u64, Sender<Result<Event, Error>>)>,
+    /// The `DiskAppeared` events of the disks still there, oldest first,
+    /// with the descriptions they changed to since
+    inventory: Vec<Event>,
+}
+
+impl SharedMonitor {
+    /// Shares the command configured by `builder`
+    pub fn new(builder: MonitorBuilder) -> SharedMonitor {
+        SharedMonitor {
+            shared: Arc::new(Shared {
+                builder,
+                state: Mutex::new(State::default()),
+                starting: Mutex::new(()),
+            }),
+        }
+    }
+    /// The monitor of `diskutil activity` shared by the whole process
+    pub fn global() -> &'static SharedMonitor {
+        static GLOBAL: OnceLock<SharedMonitor> = OnceLock::new();
+        GLOBAL.get_or_init(|| SharedMonitor::new(MonitorBuilder::new()))
+    }
+    /// Receives the events from now on, starting the command if needed
+    pub fn subscribe(&self) -> Result<Subscription, Error> {
+        self.shared.subscribe(false)
+    }
+    /// Same as [`subscribe()`] but first receives the `DiskAppeared`
+    /// event of each disk that is still there, with the description it
+    /// changed to since
+    ///
+    /// [`subscribe()`]: #method.subscribe
+    pub fn subscribe_with_inventory(&self) -> Result<Subscription, Error> {
+        self.shared.subscribe(true)
+    }
+    /// How many subscriptions receive the events of the command
+    pub fn subscribers(&self) -> usize {
+        let state = self.shared.lock();
+        state
+            .running
+            .as_ref()
+            .map_or(0, |running| running.subscribers.len())
+    }
+    /// The process id of the command, if it runs
+    pub fn pid(&self) -> Option<u32> {
+        let state = self.shared.lock();
+        state.running.as_ref().and_then(|running| running.pid)
+    }
+    /// The `DiskAppeared` events of the disks that are still there, with
+    /// the description they changed to since
+    pub fn inventory(&self) -> Vec<Event> {
+        let state = self.shared.lock();
+        state
+            .running
+            .as_ref()
+            .map_or_else(Vec::new, |running| running.inventory.clone())
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // the state stays consistent even if a subscriber panicked
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    fn subscribe(self: &Arc<Shared>, with_inventory: bool) -> Result<Subscription, Error> {
+        let _starting = self.starting.lock().unwrap_or_else(|e| e.into_inner());
+        let mut state = self.lock();
+        if state.running.is_none() {
+            // spawning may take a while, during which the other methods
+            // and dropped subscriptions should not wait. Only one
+            // subscription starts the command at a time, so it still
+            // does not run once spawned.
+            drop(state);
+            let monitor = self.builder.build()?;
+            state = self.lock();
+            self.start(&mut state, monitor);
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        let generation = state.generation;
+        let running = state.running.as_mut().expect("the command was started");
+
+        let (sender, events) = channel();
+        if with_inventory {
+            for event in &running.inventory {
+                let _ = sender.send(Ok(event.clone()));
+            }
+        }
+        running.subscribers.push((id, sender));
+        Ok(Subscription {
+            shared: Arc::clone(self),
+            id,
+            generation,
+            events,
+            finished: false,
+        })
+    }
+    /// Shares the started command and starts the thread that fans its
+    /// events out
+    fn start(self: &Arc<Shared>, state: &mut State, monitor: Monitor) {
+        state.generation += 1;
+        state.running = Some(Running {
+            pid: monitor.pid(),
+            actions: monitor.actions(),
+            subscribers: Vec::new(),
+            inventory: Vec::new(),
+        });
+        let shared = Arc::clone(self);
+        let generation = state.generation;
+        thread::spawn(move || shared.fan_out(monitor, generation));
+    }
+    fn fan_out(&self, monitor: Monitor, generation: u64) {
+        for item in monitor {
+            let mut state = self.lock();
+            if state.generation != generation {
+                return;
+            }
+            let running = match state.running.as_mut() {
+                Some(running) => running,
+                // stopped meanwhile, dropping the monitor reaps the command
+                None => return,
+            };
+            if let Ok(event) = &item {
+                running.record(event);
+            }
+            running
+                .subscribers
+                .retain(|(_, sender)| sender.send(duplicate(&item)).is_ok());
+        }
+        // the command ended on its own: the next subscription starts it again
+        let mut state = self.lock();
+        if state.generation == generation {
+            state.running = None;
+        }
+    }
+    fn unsubscribe(&self, id: u64, generation: u64) {
+        let mut state = self.lock();
+        if state.generation != generation {
+            return;
+        }
+        let running = match state.running.as_mut() {
+            Some(running) => running,
+            None => return,
+        };
+        running
+            .subscribers
+            .retain(|(subscriber, _)| *subscriber != id);
+        if running.subscribers.is_empty() {
+            let _ = running.actions.send(Action::Stop);
+            state.running = None;
+        }
+    }
+}
+
+impl Running {
+    /// Keeps the inventory of disks up to date
+    fn record(&mut self, event: &Event) {
+        let key = match disk_key(event) {
+            Some(key) => key,
+            None => return,
+        };
+        match event.name() {
+            EventKind::DiskAppeared => {
+                self.inventory
+                    .retain(|known| disk_key(known).as_ref() != Some(&key));
+                self.inventory.push(event.clone());
+            }
+            EventKind::DiskDisappeared => {
+                self.inventory
+                    .retain(|known| disk_key(known).as_ref() != Some(&key));
+            }
+            EventKind::DiskDescriptionChanged => {
+                let known = self
+                    .inventory
+                    .iter_mut()
+                    .find(|known| disk_key(known).as_ref() == Some(&key));
+                if let Some(known) = known {
+                    for (key, value) in event.attributes().iter() {
+                        known.set_attribute(key, value.unwrap_or(NULL_VALUE));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// What identifies the disk of an event: its BSD name or, for disks
+/// without one such as autofs mounts, its volume path
+fn disk_key(event: &Event) -> Option<String> {
+    event
+        .bsd_name()
+        .or_else(|| event.volume_path().map(|path| path.url().to_string()))
+}
+
+fn duplicate(item: &Result<Event, Error>) -> Result<Event, Error> {
+    match item {
+        Ok(event) => Ok(event.clone()),
+        Err(e) => Err(e.duplicate()),
+    }
+}
+
+/// The events of a [`SharedMonitor`] received by one subscriber.
+///
+/// Ends once the shared command ended, e.g. with
+/// [`Error::ChildExited`]. Dropping the last subscription stops the
+/// command.
+///
+/// [`SharedMonitor`]: ./struct.SharedMonitor.html
+/// [`Error::ChildExited`]: ./enum.Error.html#variant.ChildExited
+pub struct Subscription {
+    shared: Arc<Shared>,
+    id: u64,
+    generation: u64,
+    events: Receiver<Result<Event, Error>>,
+    finished: bool,
+}
+
+impl Subscription {
+    /// Waits up to `timeout` for the next event.
+    ///
+    /// Returns `None` if no event arrived in time or if the subscription
+    /// has finished, which [`Subscription::is_finished()`] tells apart.
+    ///
+    /// [`Subscription::is_finished()`]: #method.is_finished
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<Event, Error>> {
+        match self.events.recv_timeout(timeout) {
+            Ok(item) => Some(item),
+            Err(RecvTimeoutError::Disconnected) => self.finish(),
+            Err(RecvTimeoutError::Timeout) => None,
+        }
+    }
+    /// Returns the next event if one is already available, without waiting
+    pub fn try_next(&mut self) -> Option<Result<Event, Error>> {
+        match self.events.try_recv() {
+            Ok(item) => Some(item),
+            Err(TryRecvError::Disconnected) => self.finish(),
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+    /// Whether the command has ended and every event was consumed
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+    fn finish(&mut self) -> Option<Result<Event, Error>> {
+        self.finished = true;
+        None
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Result<Event, Error>> {
+        match self.events.recv() {
+            Ok(item) => Some(item),
+            Err(_) => self.finish(),
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.shared.unsubscribe(self.id, self.generation);
+    }
+}
@@ -0,0 +1,421 @@
+//! Where the lines that become [`Event`]s come from.
+//!
+//! A [`Monitor`] reads lines from an [`EventSource`] in its thread:
+//! the `diskutil activity` command by default, but also any [`BufRead`],
+//! lines kept in memory or a TCP or Unix socket.
+//!
+//! [`Event`]: ../struct.Event.html
+//! [`Monitor`]: ../struct.Monitor.html
+//! [`EventSource`]: ./trait.EventSource.html
+//! [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
+use crate::monitor::StderrTail;
+#[cfg(unix)]
+use crate::pty::{ControlSequenceFilter, PtyReader};
+use crate::std::Error;
+#[cfg(unix)]
+use crate::transport::Transport;
+use crate::wake::{Wakeable, Waker};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Stdin};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::Path;
+use std::process::{Child, ExitStatus};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Makes a blocked [`EventSource::read_line()`] return, see
+/// [`EventSource::interrupter()`]
+pub type Interrupter = Box<dyn FnOnce() + Send>;
+
+/// A source of `diskutil activity` lines.
+///
+/// Only [`EventSource::read_line()`] is required. The other methods let
+/// a source report why it ended and release what it holds when stopped.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::{LinesSource, Monitor};
+///
+/// let source = LinesSource::new(vec![
+///     String::from("***DiskPeek ('disk3s1') Time=20220108-20:22:35.8607"),
+/// ]);
+/// let events: Vec<_> = Monitor::from_source(source).collect();
+/// assert_eq!(events.len(), 1);
+/// assert_eq!(events[0].as_ref().unwrap().bsd_name().unwrap(), "disk3s1");
+/// ```
+pub trait EventSource: Send + 'static {
+    /// Appends the next line, including its newline, to `line`.
+    ///
+    /// Returns `Ok(0)` at the end of the source. Reads may block for as
+    /// long as no line arrives: actions are handled meanwhile. An error
+    /// of kind `TimedOut` or `WouldBlock` means that the read was
+    /// interrupted before a line arrived. Bytes appended before such an
+    /// error are kept, and the next call appends the rest of their line.
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize>;
+    /// Whether the source ended before its end was read, e.g. a command
+    /// that exited while something else keeps its stdout open
+    fn has_ended(&mut self) -> Result<bool, Error> {
+        Ok(false)
+    }
+    /// Called once the source ended, returning why. `Ok(())` by default.
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Called when the monitor is stopped or failed, to release the source
+    fn stop(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Called once before reading, returns how to make a blocked read
+    /// return when the monitor is stopped, if reads may block.
+    ///
+    /// A stopped monitor then waits until the source was released by
+    /// [`EventSource::stop()`]. Without an interrupter, `None` by
+    /// default, it stops right away and the source is released once its
+    /// pending read returned.
+    fn interrupter(&mut self) -> Option<Interrupter> {
+        None
+    }
+    /// The process id behind the source, if any
+    fn pid(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// Lines kept in memory, e.g. for tests.
+#[derive(Debug, Clone, Default)]
+pub struct LinesSource {
+    lines: VecDeque<String>,
+}
+
+impl LinesSource {
+    /// Creates a source yielding the given lines, with or without newline
+    pub fn new(lines: Vec<String>) -> LinesSource {
+        LinesSource {
+            lines: lines.into(),
+        }
+    }
+}
+
+impl From<Vec<String>> for LinesSource {
+    fn from(lines: Vec<String>) -> LinesSource {
+        LinesSource::new(lines)
+    }
+}
+
+impl EventSource for LinesSource {
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize> {
+        let next = match self.lines.pop_front() {
+            Some(next) => next,
+            None => return Ok(0),
+        };
+        line.extend_from_slice(next.as_bytes());
+        if !next.ends_with('\n') {
+            line.push(b'\n');
+        }
+        Ok(next.len() + usize::from(!next.ends_with('\n')))
+    }
+}
+
+/// Any [`BufRead`], such as a file or stdin.
+///
+/// Reads cannot be interrupted, so a stopped monitor does not wait for
+/// a line to arrive: the reader is released once its read returns.
+///
+/// [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
+#[derive(Debug)]
+pub struct ReaderSource<R> {
+    reader: R,
+}
+
+impl<R: BufRead + Send + 'static> ReaderSource<R> {
+    /// Reads lines from the given reader
+    pub fn new(reader: R) -> ReaderSource<R> {
+        ReaderSource { reader }
+    }
+}
+
+impl ReaderSource<BufReader<Stdin>> {
+    /// Reads lines from stdin, e.g. `diskutil activity | my-program`
+    pub fn stdin() -> ReaderSource<BufReader<Stdin>> {
+        ReaderSource::new(BufReader::new(io::stdin()))
+    }
+}
+
+impl<R: BufRead + Send + 'static> EventSource for ReaderSource<R> {
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize> {
+        self.reader.read_until(b'\n', line)
+    }
+}
+
+/// A TCP or Unix socket, e.g. `diskutil activity` forwarded by `nc`.
+///
+/// # Example:
+///
+/// ```no_run
+/// use mac_disk_monitor::{Monitor, SocketSource};
+///
+/// let source = SocketSource::connect_tcp("127.0.0.1:4242").unwrap();
+/// for event in Monitor::from_source(source) {
+///     println!("{}", event.unwrap().to_json());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SocketSource {
+    reader: BufReader<Socket>,
+    socket: Socket,
+}
+
+#[derive(Debug)]
+enum Socket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Socket {
+    fn try_clone(&self) -> io::Result<Socket> {
+        Ok(match self {
+            Socket::Tcp(stream) => Socket::Tcp(stream.try_clone()?),
+            #[cfg(unix)]
+            Socket::Unix(stream) => Socket::Unix(stream.try_clone()?),
+        })
+    }
+    fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.shutdown(Shutdown::Both),
+        }
+    }
+}
+
+impl SocketSource {
+    /// Connects to the given TCP address
+    pub fn connect_tcp<A: ToSocketAddrs>(address: A) -> Result<SocketSource, Error> {
+        SocketSource::from_tcp(TcpStream::connect(address)?)
+    }
+    /// Reads lines from a connected TCP stream
+    pub fn from_tcp(stream: TcpStream) -> Result<SocketSource, Error> {
+        let socket = Socket::Tcp(stream.try_clone()?);
+        Ok(SocketSource {
+            reader: BufReader::new(Socket::Tcp(stream)),
+            socket,
+        })
+    }
+    /// Connects to the Unix socket at the given path
+    #[cfg(unix)]
+    pub fn connect_unix<P: AsRef<Path>>(path: P) -> Result<SocketSource, Error> {
+        SocketSource::from_unix(UnixStream::connect(path)?)
+    }
+    /// Reads lines from a connected Unix stream
+    #[cfg(unix)]
+    pub fn from_unix(stream: UnixStream) -> Result<SocketSource, Error> {
+        let socket = Socket::Unix(stream.try_clone()?);
+        Ok(SocketSource {
+            reader: BufReader::new(Socket::Unix(stream)),
+            socket,
+        })
+    }
+}
+
+impl EventSource for SocketSource {
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize> {
+        self.reader.read_until(b'\n', line)
+    }
+    fn stop(&mut self) -> Result<(), Error> {
+        match self.socket.shutdown() {
+            Err(e) if e.kind() != io::ErrorKind::NotConnected => Err(Error::from(e)),
+            _ => Ok(()),
+        }
+    }
+    fn interrupter(&mut self) -> Option<Interrupter> {
+        // shutting the socket down ends a blocked read
+        let socket = self.socket.try_clone().ok()?;
+        Some(Box::new(move || {
+            let _ = socket.shutdown();
+        }))
+    }
+}
+
+/// The stdout of a spawned command, `diskutil activity` by default, or
+/// the pseudo-terminal it runs on.
+///
+/// Created by [`MonitorBuilder::command_source()`]. The command is killed and
+/// reaped when stopped, and its exit is reported as
+/// [`Error::ChildExited`] along with the last lines of its stderr, or as
+/// [`Error::ConnectionLost`] when its transport lost the connection.
+///
+/// [`MonitorBuilder::command_source()`]: ../struct.MonitorBuilder.html#method.command_source
+/// [`Error::ChildExited`]: ../enum.Error.html#variant.ChildExited
+/// [`Error::ConnectionLost`]: ../enum.Error.html#variant.ConnectionLost
+pub struct CommandSource {
+    child: Child,
+    stdout: Box<dyn BufRead + Send>,
+    waker: Arc<Waker>,
+    stderr: StderrTail,
+    stderr_reader: Option<thread::JoinHandle<()>>,
+    exited: Option<ExitStatus>,
+    #[cfg(unix)]
+    transport: Transport,
+}
+
+impl CommandSource {
+    /// Takes over the pipes of a command spawned with piped stdout and,
+    /// optionally, piped stderr. Reads are interrupted with the waker,
+    /// which is woken once the command exited.
+    pub(crate) fn new(mut child: Child, waker: Arc<Waker>) -> CommandSource {
+        let stdout = child
+            .stdout
+            .take()
+            .expect("child did not have a handle to stdout");
+        let stdout = BufReader::new(Wakeable::new(stdout, Arc::clone(&waker)));
+        CommandSource::with_stdout(child, Box::new(stdout), waker)
+    }
+    /// Takes over a command spawned on the given pseudo-terminal, with
+    /// optionally piped stderr
+    #[cfg(unix)]
+    pub(crate) fn on_pty(child: Child, pty: PtyReader, waker: Arc<Waker>) -> CommandSource {
+        let stdout = ControlSequenceFilter::new(Wakeable::new(pty, Arc::clone(&waker)));
+        CommandSource::with_stdout(child, Box::new(BufReader::new(stdout)), waker)
+    }
+    fn with_stdout(
+        mut child: Child,
+        stdout: Box<dyn BufRead + Send>,
+        waker: Arc<Waker>,
+    ) -> CommandSource {
+        // something else, such as a child of the command, may keep its
+        // stdout open after it exited
+        waker.wake_on_exit(child.id());
+        let stderr = StderrTail::default();
+        let stderr_reader = child.stderr.take().map(|pipe| {
+            let tail = stderr.clone();
+            thread::spawn(move || tail.read_from(pipe))
+        });
+        CommandSource {
+            child,
+            stdout,
+            waker,
+            stderr,
+            stderr_reader,
+            exited: None,
+            #[cfg(unix)]
+            transport: Transport::local(),
+        }
+    }
+    /// Reports exits that mean a lost connection of the given transport
+    /// as [`Error::ConnectionLost`]
+    ///
+    /// [`Error::ConnectionLost`]: ../enum.Error.html#variant.ConnectionLost
+    #[cfg(unix)]
+    pub(crate) fn over(mut self, transport: Transport) -> CommandSource {
+        self.transport = transport;
+        self
+    }
+    /// The last lines the command printed to stderr
+    pub(crate) fn stderr(&self) -> StderrTail {
+        self.stderr.clone()
+    }
+}
+
+impl EventSource for CommandSource {
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize> {
+        self.stdout.read_until(b'\n', line)
+    }
+    fn has_ended(&mut self) -> Result<bool, Error> {
+        if self.exited.is_none() {
+            self.exited = self.child.try_wait()?;
+        }
+        Ok(self.exited.is_some())
+    }
+    fn finish(&mut self) -> Result<(), Error> {
+        let status = match self.exited {
+            Some(status) => status,
+            None => self.child.wait()?,
+        };
+        if let Some(reader) = self.stderr_reader.take() {
+            wait_briefly(reader);
+        }
+        #[cfg(unix)]
+        if self.transport.lost_connection(&status) {
+            return Err(Error::ConnectionLost {
+                host: self.transport.host_label().unwrap_or_default().to_string(),
+                status,
+                stderr_tail: self.stderr.lines(),
+            });
+        }
+        Err(Error::ChildExited {
+            status,
+            stderr_tail: self.stderr.lines(),
+        })
+    }
+    fn stop(&mut self) -> Result<(), Error> {
+        if self.exited.is_none() {
+            self.child.kill()?;
+            self.child.wait()?;
+        }
+        Ok(())
+    }
+    fn pid(&self) -> Option<u32> {
+        Some(self.child.id())
+    }
+    fn interrupter(&mut self) -> Option<Interrupter> {
+        let waker = Arc::clone(&self.waker);
+        Some(Box::new(move || waker.wake()))
+    }
+}
+
+/// Gives the stderr reader a moment to read the last lines of a command
+/// that exited, without hanging if something else keeps stderr open
+fn wait_briefly(reader: thread::JoinHandle<()>) {
+    for _ in 0..20 {
+        if reader.is_finished() {
+            let _ = reader.join();
+            return;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventSource, LinesSource, ReaderSource};
+    use k9::assert_equal;
+    use std::io::Cursor;
+
+    fn read_all<S: EventSource>(mut source: S) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = Vec::new();
+            match source.read_line(&mut line).unwrap() {
+                0 => return lines,
+                _ => lines.push(String::from_utf8(line).unwrap()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_lines_source_terminates_each_line() {
+        let source = LinesSource::new(vec![String::from("a"), String::from("b\n")]);
+        assert_equal!(read_all(source), vec!["a\n", "b\n"]);
+    }
+    #[test]
+    fn test_reader_source() {
+        let source = ReaderSource::new(Cursor::new("a\nb"));
+        assert_equal!(read_all(source), vec!["a\n", "b"]);
+    }
+}
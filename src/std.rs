@@ -1,16 +1,16 @@
 #![allow(rustdoc::bare_urls)]
 
+use crate::control::{EventFilter, MonitorStatus, PausePolicy};
 use crate::event::Event;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::process::Command;
-use std::process::Stdio;
-use std::sync::mpsc::SendError;
-use std::time::Duration;
-use thiserror;
-use timeout_readwrite::TimeoutReader;
-
-use std::sync::mpsc::{channel, Receiver};
+use crate::monitor::{Monitor, MonitorBuilder};
+use crate::parser::{ParseError, ParseOptions};
+#[cfg(unix)]
+use crate::pty::WindowSize;
+use crate::source::EventSource;
+use std::any::Any;
+use std::process::ExitStatus;
+
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
 
 /// The error type for this crate
@@ -20,30 +20,159 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("utf-8 error: {0}")]
     Utf8(#[from] std::string::FromUtf8Error),
-    #[error("thread send error: {0}")]
-    SendError(#[from] SendError<Option<Event>>),
+    #[error("thread send error: the receiver was dropped")]
+    SendError,
+    /// The command could not be started, e.g. because it does not exist
+    #[error("failed to spawn {command:?}: {source}")]
+    Spawn {
+        command: String,
+        source: std::io::Error,
+    },
+    /// The command exited without being stopped
+    #[error("{}", exited_message(.status, .stderr_tail))]
+    ChildExited {
+        status: ExitStatus,
+        /// The last lines the command printed to stderr, oldest first
+        stderr_tail: Vec<String>,
+    },
+    /// Reading the output of the command failed
+    #[error("failed to read the output of the command: {0}")]
+    ReadFailed(std::io::Error),
+    /// The transport lost its connection to the host, e.g. `ssh` exited
+    /// with status 255
+    #[error("{}", lost_message(.host, .stderr_tail))]
+    ConnectionLost {
+        host: String,
+        status: ExitStatus,
+        /// The last lines the transport printed to stderr, oldest first
+        stderr_tail: Vec<String>,
+    },
+    /// A line printed by the command could not be parsed
+    #[error("{source} in line {line:?}")]
+    Parse { line: String, source: ParseError },
+    /// The thread that monitors the command panicked
+    #[error("the monitor thread panicked: {0}")]
+    ThreadPanicked(String),
+}
+
+impl Error {
+    /// A copy of this error, so that it can both be sent to the
+    /// consumer and returned by the thread
+    pub(crate) fn duplicate(&self) -> Error {
+        let io = |e: &std::io::Error| std::io::Error::new(e.kind(), e.to_string());
+        match self {
+            Error::Io(e) => Error::Io(io(e)),
+            Error::Utf8(e) => Error::Utf8(e.clone()),
+            Error::SendError => Error::SendError,
+            Error::Spawn { command, source } => Error::Spawn {
+                command: command.clone(),
+                source: io(source),
+            },
+            Error::ChildExited {
+                status,
+                stderr_tail,
+            } => Error::ChildExited {
+                status: *status,
+                stderr_tail: stderr_tail.clone(),
+            },
+            Error::ConnectionLost {
+                host,
+                status,
+                stderr_tail,
+            } => Error::ConnectionLost {
+                host: host.clone(),
+                status: *status,
+                stderr_tail: stderr_tail.clone(),
+            },
+            Error::ReadFailed(e) => Error::ReadFailed(io(e)),
+            Error::Parse { line, source } => Error::Parse {
+                line: line.clone(),
+                source: source.clone(),
+            },
+            Error::ThreadPanicked(message) => Error::ThreadPanicked(message.clone()),
+        }
+    }
+    /// The error of a thread that panicked with the given payload
+    pub(crate) fn panicked(payload: Box<dyn Any + Send>) -> Error {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => String::from("unknown panic payload"),
+            },
+        };
+        Error::ThreadPanicked(message)
+    }
+}
+
+fn exited_message(status: &ExitStatus, stderr_tail: &[String]) -> String {
+    match stderr_tail.last() {
+        Some(last) => format!("command exited with {}: {}", status, last),
+        None => format!("command exited with {}", status),
+    }
 }
 
-/// The Action that can be sent to the thread to stop it
-#[derive(Debug, PartialEq, Eq)]
+fn lost_message(host: &str, stderr_tail: &[String]) -> String {
+    match stderr_tail.last() {
+        Some(last) => format!("lost the connection to {}: {}", host, last),
+        None => format!("lost the connection to {}", host),
+    }
+}
+
+/// The receiving end of the events, or failures, of a running command
+pub type EventReceiver = Receiver<Result<Event, Error>>;
+
+/// The Action that can be sent to the thread to control it
+///
+/// Two [`Action::Status`] compare equal whatever sender they carry.
+#[derive(Debug)]
 pub enum Action {
+    /// Kills and reaps the command and ends the thread
     Stop,
     Noop,
+    /// Keeps reading, but holds events back according to the policy
+    Pause(PausePolicy),
+    /// Delivers the events held back and forwards new ones again
+    Resume,
+    /// Delivers the events held back while staying paused
+    Flush,
+    /// Changes which events are forwarded from now on
+    SetFilter(EventFilter),
+    /// Sends a [`MonitorStatus`] to the given sender
+    ///
+    /// [`MonitorStatus`]: ./struct.MonitorStatus.html
+    Status(Sender<MonitorStatus>),
 }
 
+impl PartialEq for Action {
+    fn eq(&self, other: &Action) -> bool {
+        match (self, other) {
+            (Action::Pause(a), Action::Pause(b)) => a == b,
+            (Action::SetFilter(a), Action::SetFilter(b)) => a == b,
+            (Action::Status(_), Action::Status(_)) => true,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for Action {}
+
 /// Runs `diskutil activity` in a thread and parses its stdout in real
-/// time, emitting Option<Event> when necessary.
+/// time, emitting an [`Event`] for each line.
+///
+/// Failures are sent through the same channel as the events, and the
+/// thread returns the one that ended it, e.g. [`Error::ChildExited`].
 ///
 /// Parameters:
 /// > `action` - a [`Receiver`] where [`Action`] can be sent to the thread.
+///
 /// [`Receiver`]: https://doc.rust-lang.org/std/sync/mpsc/struct.Receiver.html
+/// [`Event`]: ./struct.Event.html
+/// [`Error::ChildExited`]: ./enum.Error.html#variant.ChildExited
 #[cfg(not(tarpaulin_include))] // TODO find a way to write a test for this
 pub fn stream_events(
     action: Receiver<Action>,
-) -> (
-    thread::JoinHandle<Result<(), Error>>,
-    Receiver<Option<Event>>,
-) {
+) -> (thread::JoinHandle<Result<(), Error>>, EventReceiver) {
     stream_events_with_command("/usr/sbin/diskutil", vec!["activity"], action)
 }
 
@@ -51,68 +180,132 @@ pub fn stream_events(
 /// data from each new line of the subprocess's stdout.
 /// This is the underlying function that does all the heavy lifting for [`stream_events`].
 ///
+/// Lines that cannot be parsed by [`Event::parse`] are sent as
+/// [`Error::Parse`] without stopping the thread. When the command exits
+/// without being stopped, [`Error::ChildExited`] is sent and returned by
+/// the thread. A command that cannot be spawned results in [`Error::Spawn`].
+///
 /// Parameters:
 /// > `command` - the command to execute
 /// > `args` - the command-line args to pass to the command
 /// > `action` - a [`Receiver`] where [`Action`] can be sent to the thread.
+///
 /// [`Receiver`]: https://doc.rust-lang.org/std/sync/mpsc/struct.Receiver.html
 /// [`stream_events`]: ./fn.stream_events.html
+/// [`Error::Parse`]: ./enum.Error.html#variant.Parse
+/// [`Error::ChildExited`]: ./enum.Error.html#variant.ChildExited
+/// [`Error::Spawn`]: ./enum.Error.html#variant.Spawn
 pub fn stream_events_with_command(
     command: &str,
     args: Vec<&str>,
     action: Receiver<Action>,
-) -> (
-    thread::JoinHandle<Result<(), Error>>,
-    Receiver<Option<Event>>,
-) {
-    let mut child = Command::new(command)
+) -> (thread::JoinHandle<Result<(), Error>>, EventReceiver) {
+    stream_events_with_options(command, args, ParseOptions::default(), action)
+}
+
+/// Same as [`stream_events_with_command`] but parses each line with
+/// the given [`ParseOptions`], e.g. to interpret `Time=` values in a
+/// time zone other than the local one.
+///
+/// [`stream_events_with_command`]: ./fn.stream_events_with_command.html
+/// [`ParseOptions`]: ./struct.ParseOptions.html
+pub fn stream_events_with_options(
+    command: &str,
+    args: Vec<&str>,
+    options: ParseOptions,
+    action: Receiver<Action>,
+) -> (thread::JoinHandle<Result<(), Error>>, EventReceiver) {
+    let builder = MonitorBuilder::new()
+        .command(command)
         .args(args)
-        .stdin(Stdio::null())
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("failed to execute diskutil");
-
-    let (sender, receiver) = channel();
-
-    // Spawn off an expensive computation
-    let handle = thread::spawn(move || {
-        let stdout = child
-            .stdout
-            .take()
-            .expect("child did not have a handle to stdout");
-
-        let mut stdout_reader =
-            BufReader::new(TimeoutReader::new(stdout, Duration::from_millis(314)));
-
-        loop {
-            let mut outbuf: Vec<u8> = Vec::new();
-            if let Ok(_bytes_read) = stdout_reader.read_until(b'\n', &mut outbuf) {
-                let line = String::from_utf8(outbuf).unwrap();
-                if line.starts_with("***Begin monitoring") {
-                    continue;
-                }
-                let event = Event::from_line(line.as_str());
-                sender.send(Some(event)).unwrap();
-            }
-            match child.try_wait() {
-                Ok(Some(_)) => break,
-                Ok(None) => match action.recv_timeout(Duration::from_millis(100)) {
-                    Ok(action) => match action {
-                        Action::Stop => {
-                            return child.kill().map_err(|e| Error::from(e));
-                        }
-                        Action::Noop => continue,
-                    },
-                    Err(_) => {}
-                },
-                Err(_) => {
-                    break;
-                }
-            }
+        .parse_options(options);
+    stream_events_with_builder(&builder, action)
+}
+
+/// Same as [`stream_events_with_command`] but runs the command on a
+/// pseudo-terminal of the given size, for commands that block-buffer
+/// their stdout unless it is a terminal. Terminal control sequences are
+/// stripped before parsing.
+///
+/// # Example:
+///
+/// ```no_run
+/// use mac_disk_monitor::{stream_events_with_pty, WindowSize};
+/// use std::sync::mpsc::channel;
+///
+/// let (_action, receiver) = channel();
+/// let (thread, events) = stream_events_with_pty(
+///     "ssh",
+///     vec!["mac.local", "diskutil", "activity"],
+///     WindowSize::default(),
+///     receiver,
+/// );
+/// for event in events {
+///     println!("{}", event.unwrap().to_json());
+/// }
+/// ```
+///
+/// [`stream_events_with_command`]: ./fn.stream_events_with_command.html
+#[cfg(unix)]
+pub fn stream_events_with_pty(
+    command: &str,
+    args: Vec<&str>,
+    size: WindowSize,
+    action: Receiver<Action>,
+) -> (thread::JoinHandle<Result<(), Error>>, EventReceiver) {
+    let builder = MonitorBuilder::new().command(command).args(args).pty(size);
+    stream_events_with_builder(&builder, action)
+}
+
+fn stream_events_with_builder(
+    builder: &MonitorBuilder,
+    action: Receiver<Action>,
+) -> (thread::JoinHandle<Result<(), Error>>, EventReceiver) {
+    match builder.spawn(action) {
+        Ok(parts) => (parts.thread, parts.events),
+        Err(e) => {
+            let (sender, receiver) = channel();
+            let _ = sender.send(Err(e.duplicate()));
+            (thread::spawn(move || Err(e)), receiver)
         }
-        Ok(())
-    });
+    }
+}
 
-    (handle, receiver)
+/// Reads lines from the given [`EventSource`] in a thread, such as a
+/// file, a socket or lines kept in memory, and parses them like
+/// [`stream_events_with_options`].
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::{stream_events_from_source, LinesSource, ParseOptions};
+/// use std::sync::mpsc::channel;
+///
+/// let source = LinesSource::new(vec![
+///     String::from("***DiskDisappeared ('disk4', DAVolumePath = '<null>') Time=20220108-20:22:35.8607"),
+/// ]);
+/// let (_action, receiver) = channel();
+/// let (thread, events) = stream_events_from_source(source, ParseOptions::default(), receiver);
+/// assert_eq!(events.recv().unwrap().unwrap().bsd_name().unwrap(), "disk4");
+/// assert!(thread.join().unwrap().is_ok());
+/// ```
+///
+/// [`EventSource`]: ./source/trait.EventSource.html
+/// [`stream_events_with_options`]: ./fn.stream_events_with_options.html
+pub fn stream_events_from_source<S: EventSource>(
+    source: S,
+    options: ParseOptions,
+    action: Receiver<Action>,
+) -> (thread::JoinHandle<Result<(), Error>>, EventReceiver) {
+    let parts = MonitorBuilder::new()
+        .parse_options(options)
+        .spawn_source(source, action);
+    (parts.thread, parts.events)
 }
+
+/// An iterator of events from a running `diskutil activity`, see [`Monitor`].
+///
+/// This is an alias kept for compatibility, new code can use [`Monitor`].
+///
+/// [`Monitor`]: ./struct.Monitor.html
+pub type EventStream = Monitor;
@@ -0,0 +1,477 @@
+use crate::control::{EventFilter, PausePolicy};
+use crate::event::Event;
+use crate::monitor::{EventSender, MonitorBuilder};
+use crate::std::{Action, Error};
+#[cfg(unix)]
+use crate::transport::Transport;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How a supervisor restarts a command that exited or failed.
+///
+/// The delay before the n-th consecutive restart is `initial_backoff *
+/// multiplier^(n-1)`, capped at `max_backoff`, then spread by up to
+/// `jitter` of itself in either direction. A command that ran for at
+/// least `reset_after` starts over from the first delay and a full budget.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::RestartPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RestartPolicy::new()
+///     .initial_backoff(Duration::from_secs(1))
+///     .max_backoff(Duration::from_secs(5))
+///     .jitter(0.0);
+/// assert_eq!(policy.backoff(1), Duration::from_secs(1));
+/// assert_eq!(policy.backoff(2), Duration::from_secs(2));
+/// assert_eq!(policy.backoff(4), Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestartPolicy {
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    max_restarts: Option<u32>,
+    reset_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> RestartPolicy {
+        RestartPolicy {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_restarts: Some(10),
+            reset_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Creates a policy allowing 10 consecutive restarts, waiting from
+    /// 500ms up to 30s between them
+    pub fn new() -> RestartPolicy {
+        RestartPolicy::default()
+    }
+    /// The delay before the first restart
+    pub fn initial_backoff(mut self, delay: Duration) -> RestartPolicy {
+        self.initial_backoff = delay;
+        self
+    }
+    /// The longest delay between two restarts
+    pub fn max_backoff(mut self, delay: Duration) -> RestartPolicy {
+        self.max_backoff = delay;
+        self
+    }
+    /// How much longer each delay is than the previous one, at least 1
+    pub fn multiplier(mut self, multiplier: f64) -> RestartPolicy {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+    /// The fraction, between 0 and 1, by which each delay is randomly
+    /// shortened or lengthened so that many agents do not restart at once
+    pub fn jitter(mut self, jitter: f64) -> RestartPolicy {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+    /// How many consecutive restarts are attempted before giving up
+    pub fn max_restarts(mut self, restarts: u32) -> RestartPolicy {
+        self.max_restarts = Some(restarts);
+        self
+    }
+    /// Keeps restarting the command forever
+    pub fn unlimited_restarts(mut self) -> RestartPolicy {
+        self.max_restarts = None;
+        self
+    }
+    /// How long the command must run for its failure not to count as
+    /// consecutive with the previous ones
+    pub fn reset_after(mut self, duration: Duration) -> RestartPolicy {
+        self.reset_after = duration;
+        self
+    }
+    /// The delay before the given consecutive restart, starting at 1,
+    /// without jitter
+    pub fn backoff(&self, restart: u32) -> Duration {
+        let exponent = restart.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        if delay.is_finite() && delay < self.max_backoff.as_secs_f64() {
+            Duration::from_secs_f64(delay)
+        } else {
+            self.max_backoff
+        }
+    }
+    /// The delay before the given consecutive restart, with jitter
+    pub fn jittered_backoff(&self, restart: u32) -> Duration {
+        let spread = self.jitter * (2.0 * random_unit() - 1.0);
+        self.backoff(restart).mul_f64(1.0 + spread)
+    }
+    fn allows(&self, restart: u32) -> bool {
+        self.max_restarts.map_or(true, |max| restart <= max)
+    }
+}
+
+/// What a supervised command sends through its channel: the events and
+/// failures of each run, and notifications about the runs themselves.
+///
+/// The notifications never come from `diskutil`, and carry the host
+/// label of the monitor, if any.
+#[derive(Debug)]
+// the notifications are rare, boxing the events would only get in the way
+#[allow(clippy::large_enum_variant)]
+pub enum SupervisorEvent {
+    /// An event, or a failure, of the current run of the command
+    Monitor(Result<Event, Error>),
+    /// The command was spawned for the first time, possibly after
+    /// attempts that failed to spawn it
+    Started {
+        pid: Option<u32>,
+        host: Option<String>,
+    },
+    /// The command was restarted: events may have been missed in between
+    Restarted {
+        restart: u32,
+        pid: Option<u32>,
+        host: Option<String>,
+    },
+    /// The command is not restarted anymore after the given amount of
+    /// consecutive restarts, the thread returns the last failure
+    GaveUp { restarts: u32, host: Option<String> },
+}
+
+/// The receiving end of a supervised command, see [`SupervisorEvent`]
+pub type SupervisorReceiver = Receiver<SupervisorEvent>;
+
+/// Runs `diskutil activity` like [`stream_events`], restarting it
+/// according to `policy` whenever it exits or fails.
+///
+/// See [`supervise`].
+///
+/// [`stream_events`]: ./fn.stream_events.html
+/// [`supervise`]: ./fn.supervise.html
+#[cfg(not(tarpaulin_include))]
+pub fn stream_events_supervised(
+    policy: RestartPolicy,
+    action: Receiver<Action>,
+) -> (thread::JoinHandle<Result<(), Error>>, SupervisorReceiver) {
+    supervise(MonitorBuilder::new(), policy, action)
+}
+
+/// Runs the given command like [`stream_events_with_command`],
+/// restarting it according to `policy` whenever it exits or fails.
+///
+/// See [`supervise`].
+///
+/// [`stream_events_with_command`]: ./fn.stream_events_with_command.html
+/// [`supervise`]: ./fn.supervise.html
+pub fn stream_events_supervised_with_command(
+    command: &str,
+    args: Vec<&str>,
+    policy: RestartPolicy,
+    action: Receiver<Action>,
+) -> (thread::JoinHandle<Result<(), Error>>, SupervisorReceiver) {
+    supervise(
+        MonitorBuilder::new().command(command).args(args),
+        policy,
+        action,
+    )
+}
+
+/// Runs `diskutil activity` through the given transport, such as `ssh`,
+/// reconnecting according to `policy` whenever the connection is lost.
+/// Events are labeled with the host of the transport.
+///
+/// See [`supervise`].
+///
+/// [`supervise`]: ./fn.supervise.html
+#[cfg(unix)]
+#[cfg(not(tarpaulin_include))]
+pub fn stream_events_supervised_with_transport(
+    transport: Transport,
+    policy: RestartPolicy,
+    action: Receiver<Action>,
+) -> (thread::JoinHandle<Result<(), Error>>, SupervisorReceiver) {
+    supervise(MonitorBuilder::new().transport(transport), policy, action)
+}
+
+/// Runs the command configured by `builder` in a thread and keeps it
+/// running, restarting it with exponential backoff when it exits or fails.
+///
+/// Besides the events and errors of each run, wrapped in
+/// [`SupervisorEvent::Monitor`], the channel receives
+/// [`SupervisorEvent::Started`] once the command first started,
+/// [`SupervisorEvent::Restarted`] after each restart, as coverage may have
+/// a gap, and [`SupervisorEvent::GaveUp`] once the retry budget of
+/// `policy` is exhausted. The thread then returns the last failure, or
+/// `Ok(())` when stopped with [`Action::Stop`].
+///
+/// The channel is bounded by [`MonitorBuilder::channel_capacity()`], if
+/// set, so that a slow receiver also stops the command from being read.
+///
+/// # Example:
+///
+/// ```no_run
+/// use mac_disk_monitor::{supervise, MonitorBuilder, RestartPolicy, SupervisorEvent};
+/// use std::sync::mpsc::channel;
+///
+/// let (_action, receiver) = channel();
+/// let (_thread, events) = supervise(MonitorBuilder::new(), RestartPolicy::new(), receiver);
+/// for item in events {
+///     match item {
+///         SupervisorEvent::Monitor(Ok(event)) => println!("{}", event.to_json()),
+///         SupervisorEvent::Monitor(Err(e)) => eprintln!("Error: {}", e),
+///         SupervisorEvent::Restarted { restart, .. } => {
+///             eprintln!("restart {}, events may have been missed", restart);
+///         }
+///         _ => {}
+///     }
+/// }
+/// ```
+///
+/// [`Action::Stop`]: ./enum.Action.html#variant.Stop
+/// [`MonitorBuilder::channel_capacity()`]: ./struct.MonitorBuilder.html#method.channel_capacity
+pub fn supervise(
+    builder: MonitorBuilder,
+    policy: RestartPolicy,
+    action: Receiver<Action>,
+) -> (thread::JoinHandle<Result<(), Error>>, SupervisorReceiver) {
+    let (sender, events) = builder.event_channel();
+    let router = Arc::new(Router::default());
+    let routed = Arc::clone(&router);
+    thread::spawn(move || routed.route(action));
+    let thread = thread::spawn(move || {
+        Supervisor {
+            builder,
+            policy,
+            router,
+            sender,
+        }
+        .run()
+    });
+    (thread, events)
+}
+
+/// Why a single run of the command ended
+enum RunEnd {
+    /// Stopped with [`Action::Stop`] or because nobody listens anymore
+    Stopped,
+    Failed(Error),
+}
+
+/// Hands the actions sent to a supervisor over to the current run of the
+/// command as they arrive, keeping what has to be re-applied to the next
+#[derive(Default)]
+struct Router {
+    routes: Mutex<Routes>,
+    stopped: Condvar,
+}
+
+#[derive(Default)]
+struct Routes {
+    /// The actions of the current run, if any
+    current: Option<Sender<Action>>,
+    pause: Option<PausePolicy>,
+    filter: EventFilter,
+    stopped: bool,
+}
+
+impl Router {
+    fn route(&self, action: Receiver<Action>) {
+        for next in action {
+            let mut routes = self.lock();
+            match &next {
+                Action::Stop => routes.stopped = true,
+                Action::Pause(policy) => routes.pause = Some(*policy),
+                Action::Resume => routes.pause = None,
+                Action::SetFilter(filter) => routes.filter = filter.clone(),
+                _ => {}
+            }
+            // between two runs, `Action::Status` is dropped unanswered
+            if let Some(current) = &routes.current {
+                let _ = current.send(next);
+            }
+            if routes.stopped {
+                self.stopped.notify_all();
+                return;
+            }
+        }
+    }
+    fn lock(&self) -> MutexGuard<'_, Routes> {
+        self.routes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Routes the actions to the given run, after those that were sent
+    /// to the previous ones, returning `false` if stopped already
+    fn start(&self, actions: &Sender<Action>) -> bool {
+        let mut routes = self.lock();
+        if routes.stopped {
+            return false;
+        }
+        if routes.filter != EventFilter::all() {
+            let _ = actions.send(Action::SetFilter(routes.filter.clone()));
+        }
+        if let Some(policy) = routes.pause {
+            let _ = actions.send(Action::Pause(policy));
+        }
+        routes.current = Some(actions.clone());
+        true
+    }
+    fn end(&self) {
+        self.lock().current = None;
+    }
+    /// Waits before a restart, returning `false` if stopped meanwhile
+    fn wait(&self, delay: Duration) -> bool {
+        let routes = self.lock();
+        let (routes, _) = self
+            .stopped
+            .wait_timeout_while(routes, delay, |routes| !routes.stopped)
+            .unwrap_or_else(|e| e.into_inner());
+        !routes.stopped
+    }
+}
+
+struct Supervisor {
+    builder: MonitorBuilder,
+    policy: RestartPolicy,
+    router: Arc<Router>,
+    sender: EventSender<SupervisorEvent>,
+}
+
+impl Supervisor {
+    fn run(self) -> Result<(), Error> {
+        let mut restart = 0;
+        // only runs whose command was spawned count as started
+        let mut has_started = false;
+        loop {
+            let started = Instant::now();
+            let error = match self.run_once(restart, &mut has_started) {
+                RunEnd::Stopped => return Ok(()),
+                RunEnd::Failed(error) => error,
+            };
+            if started.elapsed() >= self.policy.reset_after {
+                restart = 0;
+            }
+            restart += 1;
+            if !self.policy.allows(restart) {
+                self.emit(SupervisorEvent::GaveUp {
+                    restarts: restart - 1,
+                    host: self.host(),
+                });
+                return Err(error);
+            }
+            if !self.router.wait(self.policy.jittered_backoff(restart)) {
+                return Ok(());
+            }
+        }
+    }
+    /// Spawns the command once and forwards what it produces, while its
+    /// actions are routed to it
+    fn run_once(&self, restart: u32, has_started: &mut bool) -> RunEnd {
+        let (actions, action_receiver) = channel();
+        if !self.router.start(&actions) {
+            return RunEnd::Stopped;
+        }
+        let parts = match self.builder.spawn(action_receiver) {
+            Ok(parts) => parts,
+            Err(e) => {
+                self.router.end();
+                self.emit(SupervisorEvent::Monitor(Err(e.duplicate())));
+                return RunEnd::Failed(e);
+            }
+        };
+        let (pid, host) = (parts.pid, self.host());
+        let started = self.emit(if *has_started {
+            SupervisorEvent::Restarted { restart, pid, host }
+        } else {
+            SupervisorEvent::Started { pid, host }
+        });
+        *has_started = true;
+        // the error that ended the run is returned by its thread as well
+        let listening = started
+            && parts
+                .events
+                .iter()
+                .all(|item| self.emit(SupervisorEvent::Monitor(item)));
+        self.router.end();
+        if !listening {
+            let _ = actions.send(Action::Stop);
+            // unblocks the reader if it waits on a full channel
+            drop(parts.events);
+            let _ = parts.thread.join();
+            return RunEnd::Stopped;
+        }
+        match parts.thread.join() {
+            Ok(Ok(())) => RunEnd::Stopped,
+            Ok(Err(e)) => RunEnd::Failed(e),
+            Err(payload) => {
+                let error = Error::panicked(payload);
+                self.emit(SupervisorEvent::Monitor(Err(error.duplicate())));
+                RunEnd::Failed(error)
+            }
+        }
+    }
+    /// Sends the item, returning whether the receiver is still there
+    fn emit(&self, item: SupervisorEvent) -> bool {
+        self.sender.send(item)
+    }
+    /// The host label of the notifications
+    fn host(&self) -> Option<String> {
+        self.builder.label().map(String::from)
+    }
+}
+
+/// A random number in `[0, 1)`, good enough to spread restarts
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RestartPolicy;
+    use k9::assert_equal;
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff_grows_exponentially_up_to_the_maximum() {
+        let policy = RestartPolicy::new()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_secs(1))
+            .multiplier(3.0);
+        assert_equal!(policy.backoff(1), Duration::from_millis(100));
+        assert_equal!(policy.backoff(2), Duration::from_millis(300));
+        assert_equal!(policy.backoff(3), Duration::from_millis(900));
+        assert_equal!(policy.backoff(4), Duration::from_secs(1));
+        assert_equal!(policy.backoff(u32::MAX), Duration::from_secs(1));
+    }
+    #[test]
+    fn test_jittered_backoff_stays_within_bounds() {
+        let policy = RestartPolicy::new()
+            .initial_backoff(Duration::from_secs(1))
+            .jitter(0.25);
+        for _ in 0..100 {
+            let delay = policy.jittered_backoff(1);
+            assert_equal!(delay >= Duration::from_millis(750), true);
+            assert_equal!(delay <= Duration::from_millis(1250), true);
+        }
+        let policy = policy.jitter(0.0);
+        assert_equal!(policy.jittered_backoff(1), Duration::from_secs(1));
+    }
+    #[test]
+    fn test_retry_budget() {
+        let policy = RestartPolicy::new().max_restarts(2);
+        assert_equal!(policy.allows(2), true);
+        assert_equal!(policy.allows(3), false);
+        assert_equal!(policy.unlimited_restarts().allows(u32::MAX), true);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::parser::{ParseError, ParseErrorKind};
+use chrono::{
+    DateTime, FixedOffset, Local, LocalResult, NaiveDateTime, Offset, TimeZone, Timelike, Utc,
+};
+
+const APPLE_FORMAT: &str = "%Y%m%d-%H:%M:%S";
+
+/// How many fractional digits `diskutil activity` prints after the seconds
+pub const APPLE_FRACTION_DIGITS: usize = 4;
+
+/// The time zone in which the `Time=` values printed by `diskutil
+/// activity` are interpreted.
+///
+/// `diskutil` prints wall-clock time without any offset, so the zone
+/// must match the one of the machine that printed the line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EventTimeZone {
+    /// The time zone of the machine running this process
+    #[default]
+    Local,
+    /// Coordinated Universal Time
+    Utc,
+    /// A fixed offset from UTC
+    Fixed(FixedOffset),
+}
+
+/// What to do with a `Time=` value that happens twice in the event
+/// time zone, i.e. during the hour repeated when daylight saving time ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmbiguousTime {
+    /// Use the first occurrence, before the clocks were turned back
+    #[default]
+    Earliest,
+    /// Use the second occurrence, after the clocks were turned back
+    Latest,
+    /// Fail with a [`ParseError`]
+    ///
+    /// [`ParseError`]: ./struct.ParseError.html
+    Reject,
+}
+
+/// Parses a time printed by `diskutil activity`, e.g. `20220108-20:22:05.1438`.
+///
+/// The digits after the dot are a decimal fraction of a second, so
+/// `.1438` is 143.8 milliseconds.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::parse_apple_time;
+/// use chrono::Timelike;
+///
+/// let time = parse_apple_time("20220108-20:22:05.1438").unwrap();
+/// assert_eq!(time.nanosecond(), 143_800_000);
+/// ```
+pub fn parse_apple_time(time: &str) -> Result<NaiveDateTime, ParseError> {
+    let (seconds, fraction) = match time.find('.') {
+        Some(dot) => (&time[..dot], Some(&time[dot + 1..])),
+        None => (time, None),
+    };
+    let parsed = NaiveDateTime::parse_from_str(seconds, APPLE_FORMAT)
+        .map_err(|e| ParseError::new(ParseErrorKind::Timestamp, 0, e.to_string().as_str()))?;
+    let nanoseconds = match fraction {
+        Some(fraction) => parse_fraction(fraction).ok_or_else(|| {
+            ParseError::new(
+                ParseErrorKind::Timestamp,
+                seconds.len() + 1,
+                "expected up to 9 fractional digits",
+            )
+        })?,
+        None => 0,
+    };
+    Ok(parsed + chrono::Duration::nanoseconds(i64::from(nanoseconds)))
+}
+
+fn parse_fraction(fraction: &str) -> Option<u32> {
+    if fraction.is_empty() || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let digits: u32 = fraction.parse().ok()?;
+    Some(digits * 10u32.pow((9 - fraction.len()) as u32))
+}
+
+/// Formats a time the way `diskutil activity` prints it, e.g.
+/// `20220108-20:22:05.1438`.
+///
+/// The fraction has [`APPLE_FRACTION_DIGITS`] digits, or more when the
+/// time is more precise than that, so that no precision is lost.
+pub fn format_apple_time(time: &NaiveDateTime) -> String {
+    let nanoseconds = time.nanosecond() % 1_000_000_000;
+    let mut width = APPLE_FRACTION_DIGITS;
+    while width < 9 && nanoseconds % 10u32.pow(9 - width as u32) != 0 {
+        width += 1;
+    }
+    let fraction = nanoseconds / 10u32.pow(9 - width as u32);
+    format!("{}.{:0width$}", time.format(APPLE_FORMAT), fraction)
+}
+
+/// Converts a wall-clock time into an absolute one in the given time zone.
+pub fn resolve_time(
+    time: &NaiveDateTime,
+    timezone: EventTimeZone,
+    ambiguous: AmbiguousTime,
+) -> Result<DateTime<FixedOffset>, ParseError> {
+    let result = match timezone {
+        EventTimeZone::Local => fix(Local.from_local_datetime(time)),
+        EventTimeZone::Utc => fix(Utc.from_local_datetime(time)),
+        EventTimeZone::Fixed(offset) => offset.from_local_datetime(time),
+    };
+    pick(result, ambiguous)
+}
+
+fn fix<Tz: TimeZone>(result: LocalResult<DateTime<Tz>>) -> LocalResult<DateTime<FixedOffset>> {
+    let fixed = |time: DateTime<Tz>| time.with_timezone(&time.offset().fix());
+    match result {
+        LocalResult::None => LocalResult::None,
+        LocalResult::Single(time) => LocalResult::Single(fixed(time)),
+        LocalResult::Ambiguous(earliest, latest) => {
+            LocalResult::Ambiguous(fixed(earliest), fixed(latest))
+        }
+    }
+}
+
+fn pick(
+    result: LocalResult<DateTime<FixedOffset>>,
+    ambiguous: AmbiguousTime,
+) -> Result<DateTime<FixedOffset>, ParseError> {
+    match (result, ambiguous) {
+        (LocalResult::Single(time), _) => Ok(time),
+        (LocalResult::Ambiguous(earliest, _), AmbiguousTime::Earliest) => Ok(earliest),
+        (LocalResult::Ambiguous(_, latest), AmbiguousTime::Latest) => Ok(latest),
+        (LocalResult::Ambiguous(_, _), AmbiguousTime::Reject) => Err(ParseError::new(
+            ParseErrorKind::Timestamp,
+            0,
+            "ambiguous time in the event time zone",
+        )),
+        (LocalResult::None, _) => Err(ParseError::new(
+            ParseErrorKind::Timestamp,
+            0,
+            "time does not exist in the event time zone",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        format_apple_time, parse_apple_time, pick, resolve_time, AmbiguousTime, EventTimeZone,
+    };
+    use crate::parser::ParseErrorKind;
+    use chrono::{FixedOffset, LocalResult, NaiveDate, TimeZone, Timelike};
+    use k9::assert_equal;
+
+    #[test]
+    fn test_parse_fraction_as_decimal() {
+        assert_equal!(
+            parse_apple_time("20220108-20:22:05.1438")
+                .unwrap()
+                .nanosecond(),
+            143_800_000
+        );
+        assert_equal!(
+            parse_apple_time("20220108-20:22:05.5")
+                .unwrap()
+                .nanosecond(),
+            500_000_000
+        );
+        assert_equal!(
+            parse_apple_time("20220108-20:22:05.000001438")
+                .unwrap()
+                .nanosecond(),
+            1438
+        );
+        assert_equal!(
+            parse_apple_time("20220108-20:22:05").unwrap().nanosecond(),
+            0
+        );
+    }
+    #[test]
+    fn test_parse_invalid_fraction() {
+        let error = parse_apple_time("20220108-20:22:05.14x8").unwrap_err();
+
+        assert_equal!(error.kind(), ParseErrorKind::Timestamp);
+        assert_equal!(error.offset(), 18);
+        assert_equal!(parse_apple_time("20220108-20:22:05.").is_err(), true);
+        assert_equal!(
+            parse_apple_time("20220108-20:22:05.1234567890").is_err(),
+            true
+        );
+    }
+    #[test]
+    fn test_format_round_trip() {
+        let time = parse_apple_time("20220108-20:22:05.0438").unwrap();
+
+        assert_equal!(format_apple_time(&time), "20220108-20:22:05.0438");
+    }
+    #[test]
+    fn test_format_pads_short_fractions_and_keeps_long_ones() {
+        let short = parse_apple_time("20220108-20:22:05.14").unwrap();
+        let long = parse_apple_time("20220108-20:22:05.143812").unwrap();
+
+        assert_equal!(format_apple_time(&short), "20220108-20:22:05.1400");
+        assert_equal!(format_apple_time(&long), "20220108-20:22:05.143812");
+    }
+    #[test]
+    fn test_resolve_in_fixed_offset() {
+        let time = parse_apple_time("20220108-20:22:05.1438").unwrap();
+        let offset = FixedOffset::west_opt(5 * 3600).unwrap();
+        let resolved =
+            resolve_time(&time, EventTimeZone::Fixed(offset), AmbiguousTime::Reject).unwrap();
+
+        assert_equal!(resolved.to_rfc3339(), "2022-01-08T20:22:05.143800-05:00");
+        assert_equal!(
+            resolved.naive_utc().to_string(),
+            "2022-01-09 01:22:05.143800"
+        );
+    }
+    #[test]
+    fn test_ambiguous_times() {
+        let summer = FixedOffset::east_opt(3600).unwrap();
+        let winter = FixedOffset::east_opt(0).unwrap();
+        let time = NaiveDate::from_ymd_opt(2021, 10, 31)
+            .unwrap()
+            .and_hms_opt(1, 30, 0)
+            .unwrap();
+        let ambiguous = || {
+            LocalResult::Ambiguous(
+                summer.from_local_datetime(&time).unwrap(),
+                winter.from_local_datetime(&time).unwrap(),
+            )
+        };
+
+        assert_equal!(
+            pick(ambiguous(), AmbiguousTime::Earliest).unwrap().offset(),
+            &summer
+        );
+        assert_equal!(
+            pick(ambiguous(), AmbiguousTime::Latest).unwrap().offset(),
+            &winter
+        );
+        assert_equal!(
+            pick(ambiguous(), AmbiguousTime::Reject).unwrap_err().kind(),
+            ParseErrorKind::Timestamp
+        );
+        assert_equal!(
+            pick(LocalResult::None, AmbiguousTime::Earliest).is_err(),
+            true
+        );
+    }
+}
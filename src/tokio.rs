@@ -0,0 +1,251 @@
+//! Async counterpart of [`stream_events`], available with the `async`
+//! cargo feature.
+//!
+//! [`stream_events`]: ../fn.stream_events.html
+use crate::control::{Applied, Control};
+use crate::decoding::parse_bytes;
+use crate::event::Event;
+use crate::framing::{is_meaningful, trim_line_end};
+use crate::monitor::StderrTail;
+use crate::parser::ParseOptions;
+use crate::std::{Action, Error};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdout, Command};
+use tokio::sync::mpsc;
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// How many parsed events may wait in the stream before the reader pauses
+const BUFFER: usize = 64;
+
+/// How long to wait for the last stderr lines once the command exited
+const STDERR_GRACE: Duration = Duration::from_millis(100);
+
+/// A running `diskutil activity` whose events arrive as a [`Stream`].
+///
+/// The subprocess is killed and reaped when the stream is dropped, when
+/// its [`CancellationToken`] is cancelled or when [`Action::Stop`] is sent.
+///
+/// [`Stream`]: https://docs.rs/futures-core/0.3/futures_core/stream/trait.Stream.html
+/// [`CancellationToken`]: https://docs.rs/tokio-util/0.7/tokio_util/sync/struct.CancellationToken.html
+/// [`Action::Stop`]: ../enum.Action.html#variant.Stop
+pub struct AsyncEventStream {
+    events: mpsc::Receiver<Result<Event, Error>>,
+    cancel: CancellationToken,
+    _guard: DropGuard,
+    pid: Option<u32>,
+}
+
+impl AsyncEventStream {
+    /// The process id of the command, if it was still running when spawned
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+    /// A token that stops the stream when cancelled
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+}
+
+impl Stream for AsyncEventStream {
+    type Item = Result<Event, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+/// Runs `diskutil activity` in a tokio task and parses its stdout in
+/// real time.
+///
+/// Must be called from within a tokio runtime.
+///
+/// Parameters:
+/// > `action` - a [`Receiver`] where [`Action`] can be sent to the task.
+///
+/// # Example:
+///
+/// ```no_run
+/// use futures_util::StreamExt;
+/// use mac_disk_monitor::tokio::stream_events_async;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (_action, receiver) = tokio::sync::mpsc::channel(1);
+///     let mut events = stream_events_async(receiver).unwrap();
+///     while let Some(event) = events.next().await {
+///         println!("{}", event.unwrap().to_json());
+///     }
+/// }
+/// ```
+///
+/// [`Receiver`]: https://docs.rs/tokio/1/tokio/sync/mpsc/struct.Receiver.html
+/// [`Action`]: ../enum.Action.html
+pub fn stream_events_async(action: mpsc::Receiver<Action>) -> Result<AsyncEventStream, Error> {
+    stream_events_async_with_command("/usr/sbin/diskutil", vec!["activity"], action)
+}
+
+/// Runs the given command in a tokio task and attempts to parse event
+/// data from each new line of its stdout, like [`stream_events_with_command`].
+///
+/// [`stream_events_with_command`]: ../fn.stream_events_with_command.html
+pub fn stream_events_async_with_command(
+    command: &str,
+    args: Vec<&str>,
+    action: mpsc::Receiver<Action>,
+) -> Result<AsyncEventStream, Error> {
+    stream_events_async_with_options(
+        command,
+        args,
+        ParseOptions::default(),
+        action,
+        CancellationToken::new(),
+    )
+}
+
+/// Same as [`stream_events_async_with_command`] but parses each line
+/// with the given [`ParseOptions`] and also stops when `cancel` is cancelled.
+///
+/// Dropping the stream does not cancel `cancel` itself.
+///
+/// [`stream_events_async_with_command`]: ./fn.stream_events_async_with_command.html
+/// [`ParseOptions`]: ../struct.ParseOptions.html
+pub fn stream_events_async_with_options(
+    command: &str,
+    args: Vec<&str>,
+    options: ParseOptions,
+    action: mpsc::Receiver<Action>,
+    cancel: CancellationToken,
+) -> Result<AsyncEventStream, Error> {
+    let mut child = Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|source| Error::Spawn {
+            command: String::from(command),
+            source,
+        })?;
+    let pid = child.id();
+    let stdout = child
+        .stdout
+        .take()
+        .expect("child did not have a handle to stdout");
+
+    let cancel = cancel.child_token();
+    let (sender, events) = mpsc::channel(BUFFER);
+    tokio::spawn(read_events(
+        child,
+        stdout,
+        options,
+        action,
+        cancel.clone(),
+        sender,
+    ));
+
+    Ok(AsyncEventStream {
+        events,
+        _guard: cancel.clone().drop_guard(),
+        cancel,
+        pid,
+    })
+}
+
+async fn read_stderr(pipe: ChildStderr, tail: StderrTail) {
+    let mut lines = BufReader::new(pipe).split(b'\n');
+    while let Ok(Some(line)) = lines.next_segment().await {
+        tail.push(&line);
+    }
+}
+
+async fn read_events(
+    mut child: Child,
+    stdout: ChildStdout,
+    options: ParseOptions,
+    mut action: mpsc::Receiver<Action>,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<Result<Event, Error>>,
+) {
+    let stderr = StderrTail::default();
+    let stderr_reader = child
+        .stderr
+        .take()
+        .map(|pipe| tokio::spawn(read_stderr(pipe, stderr.clone())));
+    let mut reader = BufReader::new(stdout);
+    let mut actions_open = true;
+    let mut control = Control::new(child.id());
+    // kept across iterations because `read_until` is not cancel safe:
+    // a partially read line stays in the buffer when another branch wins
+    let mut outbuf: Vec<u8> = Vec::new();
+
+    'reading: loop {
+        let items = tokio::select! {
+            _ = cancel.cancelled() => break,
+            received = action.recv(), if actions_open => match received {
+                Some(received) => match control.apply(received) {
+                    Applied::Stop => break,
+                    Applied::Deliver(items) => items,
+                },
+                None => {
+                    actions_open = false;
+                    continue;
+                }
+            },
+            read = reader.read_until(b'\n', &mut outbuf) => match read {
+                // end of stdout: the command exited. A last line without
+                // newline, read before another branch won, is parsed first.
+                Ok(0) if outbuf.is_empty() => {
+                    let error = match child.wait().await {
+                        Ok(status) => {
+                            // gives the stderr reader a moment to catch up
+                            if let Some(reader) = stderr_reader {
+                                let _ = tokio::time::timeout(STDERR_GRACE, reader).await;
+                            }
+                            Error::ChildExited {
+                                status,
+                                stderr_tail: stderr.lines(),
+                            }
+                        }
+                        Err(e) => Error::from(e),
+                    };
+                    for item in control.take_buffer() {
+                        if sender.send(item).await.is_err() {
+                            return;
+                        }
+                    }
+                    let _ = sender.send(Err(error)).await;
+                    return;
+                }
+                Ok(_) => {
+                    let line = trim_line_end(std::mem::take(&mut outbuf));
+                    if !is_meaningful(&line) {
+                        continue;
+                    }
+                    control.admit(parse_bytes(line, &options)).into_iter().collect()
+                }
+                Err(e) => {
+                    let _ = sender.send(Err(Error::ReadFailed(e))).await;
+                    break;
+                }
+            },
+        };
+        for item in items {
+            tokio::select! {
+                _ = cancel.cancelled() => break 'reading,
+                sent = sender.send(item) => if sent.is_err() {
+                    break 'reading;
+                },
+            }
+        }
+    }
+    // kills and reaps the command
+    if let Err(e) = child.kill().await {
+        let _ = sender.send(Err(Error::from(e))).await;
+    }
+}
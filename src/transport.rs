@@ -0,0 +1,282 @@
+use std::ffi::{OsStr, OsString};
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// The exit code of `ssh` when the connection failed or was lost
+const SSH_CONNECTION_FAILED: i32 = 255;
+
+/// How the command reaches the machine whose disks are monitored, see
+/// [`MonitorBuilder::transport()`].
+///
+/// `ssh` runs the command on another host, in batch mode and with
+/// keepalives so that a lost connection ends it instead of hanging.
+/// `sudo` and custom prefixes, such as `docker exec`, run it locally.
+///
+/// # Example:
+///
+/// ```
+/// use mac_disk_monitor::Transport;
+/// use std::ffi::{OsStr, OsString};
+///
+/// let transport = Transport::ssh("build-3.local").option("-p").option("2222");
+/// let diskutil = OsStr::new("/usr/sbin/diskutil");
+/// let (program, args) = transport.wrap(diskutil, &[OsString::from("activity")]);
+/// assert_eq!(program, "ssh");
+/// assert_eq!(args.last().unwrap(), "'/usr/sbin/diskutil' 'activity'");
+/// assert_eq!(transport.host_label(), Some("build-3.local"));
+/// ```
+///
+/// [`MonitorBuilder::transport()`]: ./struct.MonitorBuilder.html#method.transport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transport {
+    kind: TransportKind,
+    program: OsString,
+    options: Vec<OsString>,
+    label: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TransportKind {
+    Local,
+    Ssh {
+        host: String,
+        alive_interval: Duration,
+        alive_count: u32,
+    },
+    Sudo,
+    Prefix,
+}
+
+impl Default for Transport {
+    fn default() -> Transport {
+        Transport::local()
+    }
+}
+
+impl Transport {
+    /// Runs the command as is
+    pub fn local() -> Transport {
+        Transport {
+            kind: TransportKind::Local,
+            program: OsString::new(),
+            options: Vec::new(),
+            label: None,
+        }
+    }
+    /// Runs the command on the given host with `ssh`, labeling events
+    /// with the host. The connection is considered lost after 3 keepalives
+    /// 5s apart went unanswered.
+    pub fn ssh<S: Into<String>>(host: S) -> Transport {
+        Transport {
+            kind: TransportKind::Ssh {
+                host: host.into(),
+                alive_interval: Duration::from_secs(5),
+                alive_count: 3,
+            },
+            program: OsString::from("ssh"),
+            options: Vec::new(),
+            label: None,
+        }
+    }
+    /// Runs the command with `sudo`, failing instead of asking for a
+    /// password
+    pub fn sudo() -> Transport {
+        Transport {
+            kind: TransportKind::Sudo,
+            program: OsString::from("sudo"),
+            options: Vec::new(),
+            label: None,
+        }
+    }
+    /// Runs the command after the given program and arguments, e.g.
+    /// `docker exec my-container`
+    pub fn prefix<P, I, S>(program: P, args: I) -> Transport
+    where
+        P: Into<OsString>,
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        Transport {
+            kind: TransportKind::Prefix,
+            program: program.into(),
+            options: args.into_iter().map(Into::into).collect(),
+            label: None,
+        }
+    }
+    /// Runs the given program instead of `ssh` or `sudo`, e.g. a wrapper
+    /// script
+    pub fn program<P: Into<OsString>>(mut self, program: P) -> Transport {
+        self.program = program.into();
+        self
+    }
+    /// Appends an option, passed before the host for `ssh`
+    pub fn option<S: Into<OsString>>(mut self, option: S) -> Transport {
+        self.options.push(option.into());
+        self
+    }
+    /// How often `ssh` checks that the host still answers, and how many
+    /// unanswered checks mean that the connection is lost
+    pub fn keepalive(mut self, interval: Duration, count: u32) -> Transport {
+        if let TransportKind::Ssh {
+            alive_interval,
+            alive_count,
+            ..
+        } = &mut self.kind
+        {
+            *alive_interval = interval;
+            *alive_count = count;
+        }
+        self
+    }
+    /// Labels events with the given host instead of the `ssh` host
+    pub fn label<S: Into<String>>(mut self, label: S) -> Transport {
+        self.label = Some(label.into());
+        self
+    }
+    /// The host events are labeled with, if any
+    pub fn host_label(&self) -> Option<&str> {
+        match (&self.label, &self.kind) {
+            (Some(label), _) => Some(label),
+            (None, TransportKind::Ssh { host, .. }) => Some(host),
+            (None, _) => None,
+        }
+    }
+    /// The program to run, and its arguments, to run the given command
+    /// over this transport
+    pub fn wrap(&self, command: &OsStr, args: &[OsString]) -> (OsString, Vec<OsString>) {
+        let mut wrapped = self.options.clone();
+        match &self.kind {
+            TransportKind::Local => return (command.to_os_string(), args.to_vec()),
+            TransportKind::Ssh {
+                host,
+                alive_interval,
+                alive_count,
+            } => {
+                let mut ssh = vec![
+                    OsString::from("-T"),
+                    OsString::from("-o"),
+                    OsString::from("BatchMode=yes"),
+                    OsString::from("-o"),
+                    OsString::from(format!(
+                        "ServerAliveInterval={}",
+                        alive_interval.as_secs().max(1)
+                    )),
+                    OsString::from("-o"),
+                    OsString::from(format!("ServerAliveCountMax={}", alive_count)),
+                ];
+                ssh.append(&mut wrapped);
+                wrapped = ssh;
+                wrapped.push(OsString::from(host));
+                wrapped.push(OsString::from("--"));
+                // the remote shell splits the command again
+                let remote: Vec<String> = std::iter::once(command)
+                    .chain(args.iter().map(OsString::as_os_str))
+                    .map(|arg| shell_quote(&arg.to_string_lossy()))
+                    .collect();
+                wrapped.push(OsString::from(remote.join(" ")));
+                return (self.program.clone(), wrapped);
+            }
+            TransportKind::Sudo => wrapped.insert(0, OsString::from("-n")),
+            TransportKind::Prefix => {}
+        }
+        wrapped.push(command.to_os_string());
+        wrapped.extend(args.iter().cloned());
+        (self.program.clone(), wrapped)
+    }
+    /// Whether a command that exited with the given status lost its
+    /// connection rather than ended
+    pub(crate) fn lost_connection(&self, status: &ExitStatus) -> bool {
+        matches!(self.kind, TransportKind::Ssh { .. })
+            && status.signal().is_none()
+            && status.code() == Some(SSH_CONNECTION_FAILED)
+    }
+}
+
+/// The argument quoted for a POSIX shell
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transport;
+    use k9::assert_equal;
+    use std::ffi::{OsStr, OsString};
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::time::Duration;
+
+    fn wrapped(transport: &Transport) -> Vec<String> {
+        let (program, args) = transport.wrap(
+            OsStr::new("/usr/sbin/diskutil"),
+            &[OsString::from("activity"), OsString::from("it's")],
+        );
+        std::iter::once(program)
+            .chain(args)
+            .map(|arg| arg.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_local() {
+        assert_equal!(
+            wrapped(&Transport::local()),
+            vec!["/usr/sbin/diskutil", "activity", "it's"]
+        );
+        assert_equal!(Transport::local().host_label(), None);
+    }
+    #[test]
+    fn test_ssh_quotes_the_remote_command() {
+        let transport = Transport::ssh("mac")
+            .option("-p")
+            .option("2222")
+            .keepalive(Duration::from_secs(2), 4);
+        assert_equal!(
+            wrapped(&transport),
+            vec![
+                "ssh",
+                "-T",
+                "-o",
+                "BatchMode=yes",
+                "-o",
+                "ServerAliveInterval=2",
+                "-o",
+                "ServerAliveCountMax=4",
+                "-p",
+                "2222",
+                "mac",
+                "--",
+                r"'/usr/sbin/diskutil' 'activity' 'it'\''s'",
+            ]
+        );
+        assert_equal!(transport.host_label(), Some("mac"));
+        assert_equal!(transport.label("build-1").host_label(), Some("build-1"));
+    }
+    #[test]
+    fn test_sudo_and_prefix() {
+        assert_equal!(
+            wrapped(&Transport::sudo().program("doas")),
+            vec!["doas", "-n", "/usr/sbin/diskutil", "activity", "it's"]
+        );
+        assert_equal!(
+            wrapped(&Transport::prefix("docker", vec!["exec", "ci"])),
+            vec![
+                "docker",
+                "exec",
+                "ci",
+                "/usr/sbin/diskutil",
+                "activity",
+                "it's"
+            ]
+        );
+    }
+    #[test]
+    fn test_lost_connection() {
+        let lost = ExitStatus::from_raw(255 << 8);
+        assert_equal!(Transport::ssh("mac").lost_connection(&lost), true);
+        assert_equal!(Transport::sudo().lost_connection(&lost), false);
+        let failed = ExitStatus::from_raw(1 << 8);
+        assert_equal!(Transport::ssh("mac").lost_connection(&failed), false);
+    }
+}
@@ -0,0 +1,183 @@
+//! Lets another thread interrupt a read that blocks on a file
+//! descriptor, so that reads need no timeout to notice a stop or the
+//! exit of a command whose output something else keeps open.
+//!
+//! Elsewhere than on Unix, reads cannot be interrupted.
+#[cfg(unix)]
+use std::io::ErrorKind;
+use std::io::{self, Read};
+#[cfg(unix)]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(unix)]
+use std::thread;
+
+/// Both ends of a self-pipe, written to once woken
+#[derive(Debug)]
+pub(crate) struct Waker {
+    #[cfg(unix)]
+    read: OwnedFd,
+    #[cfg(unix)]
+    write: OwnedFd,
+    #[cfg(unix)]
+    woken: AtomicBool,
+}
+
+#[cfg(unix)]
+impl Waker {
+    pub(crate) fn new() -> io::Result<Arc<Waker>> {
+        let mut fds: [libc::c_int; 2] = [-1; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        set_cloexec(read.as_raw_fd())?;
+        set_cloexec(write.as_raw_fd())?;
+        Ok(Arc::new(Waker {
+            read,
+            write,
+            woken: AtomicBool::new(false),
+        }))
+    }
+    /// From now on, reads of a [`Wakeable`] that would block fail with
+    /// `TimedOut` instead
+    pub(crate) fn wake(&self) {
+        if !self.woken.swap(true, Ordering::SeqCst) {
+            let byte = 1u8;
+            let _ =
+                unsafe { libc::write(self.write.as_raw_fd(), std::ptr::addr_of!(byte).cast(), 1) };
+        }
+    }
+    /// Wakes once the given child process exited, without reaping it
+    pub(crate) fn wake_on_exit(self: &Arc<Waker>, pid: u32) {
+        let waker = Arc::clone(self);
+        thread::spawn(move || {
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            while unsafe {
+                libc::waitid(
+                    libc::P_PID,
+                    pid as libc::id_t,
+                    &mut info,
+                    libc::WEXITED | libc::WNOWAIT,
+                )
+            } != 0
+            {
+                if io::Error::last_os_error().kind() != ErrorKind::Interrupted {
+                    // already reaped
+                    break;
+                }
+            }
+            waker.wake();
+        });
+    }
+}
+
+#[cfg(not(unix))]
+impl Waker {
+    pub(crate) fn new() -> io::Result<Arc<Waker>> {
+        Ok(Arc::new(Waker {}))
+    }
+    pub(crate) fn wake(&self) {}
+    pub(crate) fn wake_on_exit(self: &Arc<Waker>, _pid: u32) {}
+}
+
+/// A reader that blocks until it has something to read or its
+/// [`Waker`] was woken. What is left to read is read first.
+pub(crate) struct Wakeable<R> {
+    inner: R,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    waker: Arc<Waker>,
+}
+
+impl<R: Read> Wakeable<R> {
+    pub(crate) fn new(inner: R, waker: Arc<Waker>) -> Wakeable<R> {
+        Wakeable { inner, waker }
+    }
+}
+
+#[cfg(not(unix))]
+impl<R: Read> Read for Wakeable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(unix)]
+impl<R: Read + AsRawFd> Read for Wakeable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut fds = [
+            libc::pollfd {
+                fd: self.inner.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: self.waker.read.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        loop {
+            if unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+            // also readable once closed or failed, which the read reports
+            if fds[0].revents != 0 {
+                return self.inner.read(buf);
+            }
+            if fds[1].revents != 0 {
+                return Err(io::Error::new(ErrorKind::TimedOut, "woken up"));
+            }
+        }
+    }
+}
+
+/// Keeps the file descriptor from being inherited by commands
+#[cfg(unix)]
+pub(crate) fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{Wakeable, Waker};
+    use k9::assert_equal;
+    use std::io::{ErrorKind, Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_wake_interrupts_a_blocked_read() {
+        let (mut writer, reader) = UnixStream::pair().unwrap();
+        let waker = Waker::new().unwrap();
+        let mut reader = Wakeable::new(reader, waker.clone());
+
+        writer.write_all(b"left").unwrap();
+        let wake = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            waker.wake();
+        });
+        let mut buf = [0; 8];
+        assert_equal!(reader.read(&mut buf).unwrap(), 4);
+        assert_equal!(
+            reader.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::TimedOut
+        );
+        wake.join().unwrap();
+        assert_equal!(
+            reader.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::TimedOut
+        );
+    }
+}
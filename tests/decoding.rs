@@ -0,0 +1,59 @@
+use k9::assert_equal;
+use mac_disk_monitor::{
+    Decoding, Error, EventKind, Monitor, MonitorBuilder, ParseOptions, ReaderSource,
+};
+use std::io::Cursor;
+
+/// A FAT volume name written in a legacy codepage, between two valid lines
+fn log() -> Vec<u8> {
+    let mut log = b"***DiskPeek ('disk1') Time=20220108-20:22:35.8607\n".to_vec();
+    log.extend_from_slice(b"***DiskAppeared ('disk2s1', DAVolumeKind = 'msdos', DAVolumeName = 'Cl\x8e USB') Time=20220108-20:22:36.0000\n");
+    log.extend_from_slice(b"***DAIdle (no DADiskRef) Time=20220108-20:22:36.1000\n");
+    log
+}
+
+fn monitor(decoding: Decoding) -> Monitor {
+    MonitorBuilder::new()
+        .parse_options(ParseOptions {
+            decoding,
+            ..ParseOptions::default()
+        })
+        .build_from_source(ReaderSource::new(Cursor::new(log())))
+}
+
+#[test]
+fn test_strict_decoding_reports_the_offending_bytes_and_goes_on() {
+    let items: Vec<_> = monitor(Decoding::Strict).collect();
+
+    assert_equal!(items.len(), 3);
+    assert_equal!(items[0].as_ref().unwrap().name(), EventKind::DiskPeek);
+    match &items[1] {
+        Err(Error::Utf8(e)) => {
+            assert_equal!(e.as_bytes().ends_with(b"Time=20220108-20:22:36.0000"), true);
+            assert_equal!(e.as_bytes()[e.utf8_error().valid_up_to()], 0x8e);
+        }
+        other => panic!("expected Error::Utf8, got {:?}", other),
+    }
+    assert_equal!(items[2].as_ref().unwrap().name(), EventKind::DAIdle);
+}
+
+#[test]
+fn test_lossy_decoding_flags_the_event() {
+    let events: Vec<_> = monitor(Decoding::Lossy).map(Result::unwrap).collect();
+
+    assert_equal!(events.len(), 3);
+    assert_equal!(events[0].is_lossy(), false);
+    assert_equal!(events[1].is_lossy(), true);
+    assert_equal!(events[1].volume_name().unwrap(), "Cl\u{fffd} USB");
+    assert_equal!(events[1].to_json().contains(r#""lossy":true"#), true);
+    assert_equal!(events[0].to_json().contains("lossy"), false);
+}
+
+#[test]
+fn test_mac_roman_decoding() {
+    let events: Vec<_> = monitor(Decoding::MacRoman).map(Result::unwrap).collect();
+
+    assert_equal!(events.len(), 3);
+    assert_equal!(events[1].volume_name().unwrap(), "Clé USB");
+    assert_equal!(events[1].is_lossy(), false);
+}
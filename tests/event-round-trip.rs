@@ -0,0 +1,155 @@
+use chrono::{TimeZone, Utc};
+use k9::assert_equal;
+use mac_disk_monitor::{Event, EventTimeZone, ParseOptions};
+use proptest::prelude::*;
+
+fn utc() -> ParseOptions {
+    ParseOptions {
+        timezone: EventTimeZone::Utc,
+        ..ParseOptions::default()
+    }
+}
+
+fn parse(line: &str) -> Event {
+    let mut event = Event::parse_with(line, &utc()).unwrap();
+    event.set_received_at(Utc.timestamp_opt(0, 0).unwrap());
+    event
+}
+
+fn dummy_lines() -> Vec<String> {
+    include_str!("dummy-disk-activity.sh")
+        .lines()
+        .filter_map(|line| line.strip_prefix("emit \""))
+        .filter_map(|line| line.strip_suffix('"'))
+        .filter(|line| !line.starts_with("***Begin monitoring"))
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn test_render_dummy_lines_byte_exact() {
+    let lines = dummy_lines();
+    assert_equal!(lines.is_empty(), false);
+
+    for line in lines {
+        assert_equal!(parse(&line).to_diskutil_line(), line);
+    }
+}
+
+#[test]
+fn test_render_tricky_lines_is_stable() {
+    for case in include_str!("corpus/tricky-lines.jsonl").lines() {
+        let case: serde_json::Value = serde_json::from_str(case).unwrap();
+        let event = parse(case["line"].as_str().unwrap());
+        let rendered = event.to_diskutil_line();
+
+        assert_equal!((rendered.clone(), parse(&rendered)), (rendered, event));
+    }
+}
+
+#[test]
+fn test_render_backslashes_verbatim() {
+    for line in [
+        "***DiskAppeared ('disk7s1', DAVolumePath = '<null>', DAVolumeKind = 'msdos', DAVolumeName = 'Bob\\'s Drive') Time=20220108-20:22:05.1438",
+        "***DiskAppeared ('disk7s2', DAVolumePath = '<null>', DAVolumeKind = 'ntfs', DAVolumeName = 'C:\\') Time=20220108-20:22:05.1438",
+        "***DiskAppeared ('disk7s3', DAVolumePath = '<null>', DAVolumeKind = 'ntfs', DAVolumeName = 'a\\\\b') Time=20220108-20:22:05.1438",
+    ] {
+        assert_equal!(parse(line).to_diskutil_line(), line);
+    }
+}
+
+#[test]
+fn test_render_normalizes_fraction_width() {
+    let short = "***DiskPeek ('disk3s1') Time=20220108-20:22:05.14";
+    let long = "***DiskPeek ('disk3s1') Time=20220108-20:22:05.143812";
+
+    assert_equal!(
+        parse(short).to_diskutil_line(),
+        "***DiskPeek ('disk3s1') Time=20220108-20:22:05.1400"
+    );
+    assert_equal!(parse(long).to_diskutil_line(), long);
+}
+
+fn value() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::from("<null>")),
+        "[A-Za-z0-9 ._%/(),'-]{0,24}",
+        "file:///Volumes/[A-Za-z0-9%]{1,12}/",
+    ]
+}
+
+fn disk_ref() -> impl Strategy<Value = String> {
+    prop_oneof![
+        (0u32..20, proptest::collection::vec(1u32..12, 0..3)).prop_map(|(disk, slices)| {
+            let slices: String = slices.iter().map(|s| format!("s{}", s)).collect();
+            format!("'disk{}{}'", disk, slices)
+        }),
+        Just(String::from("(no BSD name)")),
+        Just(String::from("no DADiskRef")),
+    ]
+}
+
+prop_compose! {
+    fn line(fraction: &'static str)(
+        name in prop_oneof![
+            Just("DiskAppeared"),
+            Just("DiskDisappeared"),
+            Just("DiskDescriptionChanged"),
+            Just("DiskPeek"),
+            Just("DiskEjectApproval"),
+            Just("DAIdle"),
+            Just("DiskSomethingNew"),
+        ],
+        disk_ref in disk_ref(),
+        attributes in proptest::collection::vec(
+            (prop_oneof![
+                Just("DAVolumePath"),
+                Just("DAVolumeKind"),
+                Just("DAVolumeName"),
+                Just("DAMediaSize"),
+                Just("DAMediaName"),
+            ], value()),
+            0..5,
+        ),
+        comment in proptest::option::of(prop_oneof![
+            Just(String::from("Approving")),
+            "Dissenting \\(status = 0x[0-9a-f]{8}\\)",
+        ]),
+        time in (2000i64..2040, 1u32..13, 1u32..29, 0u32..24, 0u32..60, 0u32..60, fraction),
+    ) -> String {
+        let mut seen = Vec::new();
+        let attributes: String = attributes
+            .into_iter()
+            .filter(|(key, _)| {
+                let new = !seen.contains(key);
+                seen.push(*key);
+                new
+            })
+            .map(|(key, value)| format!(", {} = '{}'", key, value))
+            .collect();
+        let comment = comment.map(|c| format!(" Comment={}", c)).unwrap_or_default();
+        let (year, month, day, hour, minute, second, fraction) = time;
+        format!(
+            "***{} ({}{}){} Time={}{:02}{:02}-{:02}:{:02}:{:02}.{}",
+            name, disk_ref, attributes, comment, year, month, day, hour, minute, second, fraction
+        )
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_parse_render_parse_is_stable(line in line("[0-9]{4}")) {
+        let event = parse(&line);
+        let rendered = event.to_diskutil_line();
+
+        prop_assert_eq!(&rendered, &line);
+        prop_assert_eq!(parse(&rendered), event);
+    }
+
+    #[test]
+    fn test_render_keeps_any_fraction(line in line("[0-9]{1,9}")) {
+        let event = parse(&line);
+
+        prop_assert_eq!(parse(&event.to_diskutil_line()), event);
+    }
+}
@@ -0,0 +1,165 @@
+use k9::assert_equal;
+use mac_disk_monitor::{
+    stream_events_from_source, Action, EventKind, EventSource, LinesSource, Monitor,
+    MonitorBuilder, ParseOptions, ReaderSource, ReplaySource, SocketSource,
+};
+use std::io::{BufReader, Cursor, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LINES: &[&str] = &[
+    "***Begin monitoring DiskArbitration activity",
+    "***DiskAppeared ('disk4', DAVolumePath = 'file:///Volumes/my%20backups/', DAVolumeKind = 'hfs', DAVolumeName = 'Time Machine Backups') Time=20220108-20:22:05.1438",
+    "",
+    "***DiskPeek ('disk4') Time=20220108-20:22:35.8607",
+    "***DAIdle (no DADiskRef) Time=20220108-20:22:35.9144",
+];
+
+fn names<S: EventSource>(source: S) -> Vec<EventKind> {
+    Monitor::from_source(source)
+        .map(|event| event.unwrap().name())
+        .collect()
+}
+
+#[test]
+fn test_lines_source() {
+    let source = LinesSource::new(LINES.iter().map(|line| line.to_string()).collect());
+    assert_equal!(
+        names(source),
+        vec![
+            EventKind::DiskAppeared,
+            EventKind::DiskPeek,
+            EventKind::DAIdle
+        ]
+    );
+}
+
+#[test]
+fn test_reader_source() {
+    let source = ReaderSource::new(Cursor::new(LINES.join("\n")));
+    assert_equal!(names(source).len(), 3);
+}
+
+#[test]
+fn test_reader_source_reports_parse_errors() {
+    let source = ReaderSource::new(Cursor::new(
+        "garbage\n***DiskPeek ('disk4') Time=20220108-20:22:35.8607\n",
+    ));
+    let (_action, receiver) = channel();
+    let (thread, events) = stream_events_from_source(source, ParseOptions::default(), receiver);
+
+    assert_equal!(events.recv().unwrap().is_err(), true);
+    assert_equal!(events.recv().unwrap().unwrap().name(), EventKind::DiskPeek);
+    assert_equal!(events.recv().is_err(), true);
+    assert_equal!(thread.join().unwrap().is_ok(), true);
+}
+
+#[test]
+fn test_tcp_socket_source() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        for line in LINES {
+            writeln!(stream, "{}", line).unwrap();
+        }
+    });
+
+    let source = SocketSource::connect_tcp(address).unwrap();
+    assert_equal!(names(source).len(), 3);
+    server.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_source() {
+    use std::os::unix::net::UnixListener;
+
+    let path = std::env::temp_dir().join(format!("mac-disk-monitor-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        for line in LINES {
+            writeln!(stream, "{}", line).unwrap();
+        }
+    });
+
+    let source = SocketSource::connect_unix(&path).unwrap();
+    assert_equal!(names(source).len(), 3);
+    server.join().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_stop_action_stops_an_idle_socket() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || listener.accept().unwrap());
+
+    let source = SocketSource::connect_tcp(address).unwrap();
+    let monitor = MonitorBuilder::new().build_from_source(source);
+    assert_equal!(monitor.pid(), None);
+    let _connection = server.join().unwrap();
+
+    monitor.actions().send(Action::Stop).unwrap();
+    assert_equal!(monitor.stop().is_ok(), true);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stop_does_not_wait_for_a_blocked_reader() {
+    use std::os::unix::net::UnixStream;
+
+    let (_writer, reader) = UnixStream::pair().unwrap();
+    let monitor = Monitor::from_source(ReaderSource::new(BufReader::new(reader)));
+
+    let stopping = Instant::now();
+    assert_equal!(monitor.stop().is_ok(), true);
+    assert_equal!(stopping.elapsed() < Duration::from_secs(1), true);
+}
+
+#[test]
+fn test_events_of_an_idle_socket_arrive_right_away() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let source = SocketSource::connect_tcp(listener.local_addr().unwrap()).unwrap();
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut monitor = Monitor::from_source(source);
+
+    for _ in 0..3 {
+        // so that the line arrives while the reader is blocked
+        thread::sleep(Duration::from_millis(350));
+        let sent = Instant::now();
+        writeln!(stream, "{}", LINES[3]).unwrap();
+        assert_equal!(monitor.next().unwrap().is_ok(), true);
+        assert_equal!(sent.elapsed() < Duration::from_millis(50), true);
+    }
+}
+
+#[test]
+fn test_command_source() {
+    let source = MonitorBuilder::new()
+        .command("./tests/dummy-disk-activity.sh")
+        .command_source()
+        .unwrap();
+    assert_equal!(source.pid().is_some(), true);
+
+    let mut monitor = Monitor::from_source(source);
+    let event = monitor.next().unwrap().unwrap();
+    assert_equal!(event.name(), EventKind::DiskAppeared);
+}
+
+#[test]
+fn test_replay_source() {
+    let source = ReplaySource::new(Cursor::new(LINES.join("\n"))).speed(1000.0);
+    assert_equal!(
+        names(source),
+        vec![
+            EventKind::DiskAppeared,
+            EventKind::DiskPeek,
+            EventKind::DAIdle
+        ]
+    );
+}
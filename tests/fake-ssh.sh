@@ -0,0 +1,30 @@
+#!/usr/bin/env bash
+# Stands in for ssh: skips the options and runs the remote command
+# locally. The host "unreachable" cannot be connected to, and when
+# $FAKE_SSH_DROP_ONCE names a file that does not exist yet, the
+# connection is lost after the first line.
+
+while [ $# -gt 0 ]; do
+    case "$1" in
+        -o|-p|-i|-l) shift 2 ;;
+        -*) shift ;;
+        *) break ;;
+    esac
+done
+host="$1"
+shift
+[ "$1" = "--" ] && shift
+
+if [ "$host" = "unreachable" ]; then
+    echo "ssh: connect to host $host port 22: Connection refused" >&2
+    exit 255
+fi
+
+if [ -n "$FAKE_SSH_DROP_ONCE" ] && [ ! -e "$FAKE_SSH_DROP_ONCE" ]; then
+    touch "$FAKE_SSH_DROP_ONCE"
+    eval "$*" | head -n 1
+    echo "Connection to $host closed by remote host." >&2
+    exit 255
+fi
+
+eval "$*"
@@ -0,0 +1,96 @@
+use k9::assert_equal;
+use mac_disk_monitor::{Error, MonitorBuilder};
+
+const LINES: &[&str] = &[
+    "***Begin monitoring DiskArbitration activity",
+    "***DiskAppeared ('disk4', DAVolumePath = 'file:///Volumes/my%20backups/', DAVolumeKind = 'hfs', DAVolumeName = 'Time Machine Backups') Time=20220108-20:22:05.1438",
+    "",
+    "***DiskAppeared ((no BSD name), DAVolumePath = 'file:///System/Volumes/Data/home/', DAVolumeKind = 'autofs', DAVolumeName = '<null>') Time=20220108-20:22:05.1457",
+    "***DiskDescriptionChanged ('disk3s1', DAVolumePath = 'file:///Volumes/EFI/') Time=20220108-20:22:35.8607",
+    "***DiskPeek ('disk3s1') Time=20220108-20:22:35.8607",
+    "   ",
+    "***DAIdle (no DADiskRef) Time=20220108-20:22:35.9144",
+    "***DiskUnmountApproval ('disk3s1', DAVolumePath = 'file:///Volumes/EFI/', DAVolumeKind = 'msdos', DAVolumeName = 'EFI') Comment=Approving Time=20220108-20:22:59.0015",
+];
+
+/// A tiny linear congruential generator, so that failures can be reproduced
+struct Lcg(u64);
+
+impl Lcg {
+    fn below(&mut self, bound: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) % bound
+    }
+}
+
+/// A shell script printing the lines in random-sized chunks with pauses
+fn chunked_script(seed: u64) -> String {
+    let mut random = Lcg(seed);
+    let text = format!("{}\n", LINES.join("\n"));
+    let bytes = text.as_bytes();
+    let mut script = String::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let end = (start + 1 + random.below(40) as usize).min(bytes.len());
+        let octal: String = bytes[start..end]
+            .iter()
+            .map(|byte| format!("\\{:03o}", byte))
+            .collect();
+        script.push_str(&format!(
+            "printf '{}'; sleep 0.0{}; ",
+            octal,
+            random.below(3)
+        ));
+        start = end;
+    }
+    script
+}
+
+#[test]
+fn test_lines_written_in_chunks_with_pauses_are_not_mangled() {
+    for seed in [1, 2, 3] {
+        let monitor = MonitorBuilder::new()
+            .command("sh")
+            .args(["-c", chunked_script(seed).as_str()])
+            .build()
+            .unwrap();
+
+        let mut names = Vec::new();
+        for item in monitor {
+            match item {
+                Ok(event) => names.push(event.name().to_string()),
+                Err(Error::ChildExited { status, .. }) => {
+                    assert_equal!(status.success(), true);
+                }
+                Err(e) => panic!("seed {}: {}", seed, e),
+            }
+        }
+        assert_equal!(
+            names,
+            vec![
+                "DiskAppeared",
+                "DiskAppeared",
+                "DiskDescriptionChanged",
+                "DiskPeek",
+                "DAIdle",
+                "DiskUnmountApproval",
+            ]
+        );
+    }
+}
+
+#[test]
+fn test_last_line_without_newline_is_not_lost() {
+    let monitor = MonitorBuilder::new()
+        .command("printf")
+        .arg("***DiskPeek ('disk1') Time=20220108-20:22:35.8607")
+        .build()
+        .unwrap();
+
+    let events: Vec<_> = monitor.filter_map(Result::ok).collect();
+    assert_equal!(events.len(), 1);
+    assert_equal!(events[0].bsd_name().unwrap(), "disk1");
+}
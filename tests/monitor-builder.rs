@@ -0,0 +1,136 @@
+use k9::assert_equal;
+use mac_disk_monitor::{Error, EventTimeZone, MonitorBuilder, ParseOptions};
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+fn peek_lines(count: usize) -> String {
+    format!(
+        "for i in $(seq 1 {}); do echo \"***DiskPeek ('disk$i') Time=20220108-20:22:05.1438\"; done; sleep 2",
+        count
+    )
+}
+
+#[test]
+fn test_build_fails_for_missing_command() {
+    let error = MonitorBuilder::new()
+        .command("./tests/does-not-exist")
+        .build()
+        .err()
+        .unwrap();
+
+    assert_equal!(
+        matches!(error, Error::Spawn { ref source, .. } if source.kind() == ErrorKind::NotFound),
+        true
+    );
+}
+
+#[test]
+fn test_env_current_dir_and_parse_options() {
+    let mut monitor = MonitorBuilder::new()
+        .command("sh")
+        .arg("-c")
+        .arg("echo \"***DiskAppeared ('$DISK', DAVolumePath = 'file://$PWD/') Time=20220108-20:22:05.1438\"; sleep 2")
+        .env("DISK", "disk7s1")
+        .current_dir("/tmp")
+        .parse_options(ParseOptions {
+            timezone: EventTimeZone::Utc,
+            ..ParseOptions::default()
+        })
+        .build()
+        .unwrap();
+
+    let event = monitor
+        .next_timeout(Duration::from_secs(5))
+        .unwrap()
+        .unwrap();
+    assert_equal!(event.bsd_name(), Some(String::from("disk7s1")));
+    assert_equal!(event.path(), Some(String::from("file:///tmp/")));
+    assert_equal!(
+        event.time().to_rfc3339(),
+        "2022-01-08T20:22:05.143800+00:00"
+    );
+}
+
+#[test]
+fn test_bounded_channel_applies_backpressure_without_losing_events() {
+    let mut monitor = MonitorBuilder::new()
+        .command("sh")
+        .args(["-c", peek_lines(20).as_str()])
+        .channel_capacity(2)
+        .build()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+
+    let mut names = Vec::new();
+    while let Some(event) = monitor.next_timeout(Duration::from_secs(1)) {
+        names.push(event.unwrap().bsd_name().unwrap());
+    }
+    assert_equal!(names.len(), 20);
+    assert_equal!(names.first().unwrap(), "disk1");
+    assert_equal!(names.last().unwrap(), "disk20");
+}
+
+#[test]
+fn test_captures_stderr_tail() {
+    let mut monitor = MonitorBuilder::new()
+        .command("sh")
+        .args([
+            "-c",
+            "for i in $(seq 1 30); do echo \"oops $i\" >&2; done; sleep 2",
+        ])
+        .build()
+        .unwrap();
+    assert_equal!(
+        monitor.next_timeout(Duration::from_millis(500)).is_none(),
+        true
+    );
+
+    let tail = monitor.stderr_tail();
+    assert_equal!(tail.len(), 20);
+    assert_equal!(tail.first().unwrap(), "oops 11");
+    assert_equal!(tail.last().unwrap(), "oops 30");
+    monitor.stop().unwrap();
+}
+
+#[test]
+fn test_exit_is_reported_while_stdout_is_kept_open() {
+    let started = Instant::now();
+    let mut monitor = MonitorBuilder::new()
+        .command("sh")
+        .args([
+            "-c",
+            "sleep 5 & echo \"***DiskPeek ('disk1') Time=20220108-20:22:05.1438\"; exit 3",
+        ])
+        .build()
+        .unwrap();
+
+    assert_equal!(monitor.next().unwrap().is_ok(), true);
+    match monitor.next() {
+        Some(Err(Error::ChildExited { status, .. })) => {
+            assert_equal!(status.code(), Some(3));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_equal!(started.elapsed() < Duration::from_secs(2), true);
+}
+
+#[test]
+fn test_stop_kills_an_idle_command_right_away() {
+    let monitor = MonitorBuilder::new()
+        .command("sleep")
+        .args(["10"])
+        .build()
+        .unwrap();
+    let pid = monitor.pid().unwrap();
+
+    let stopping = Instant::now();
+    assert_equal!(monitor.stop().is_ok(), true);
+    assert_equal!(stopping.elapsed() < Duration::from_secs(1), true);
+    let running = std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap()
+        .success();
+    assert_equal!(running, false);
+}
@@ -0,0 +1,149 @@
+use k9::assert_equal;
+use mac_disk_monitor::{
+    Action, EventFilter, EventKind, Monitor, MonitorBuilder, MonitorStatus, PausePolicy,
+    SocketSource,
+};
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A monitor of a socket whose other end the test writes to
+fn connected() -> (Monitor, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let source = SocketSource::connect_tcp(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    let monitor = MonitorBuilder::new().build_from_source(source);
+    (monitor, stream)
+}
+
+fn write_peeks(stream: &mut TcpStream, disks: &[&str]) {
+    for disk in disks {
+        writeln!(
+            stream,
+            "***DiskPeek ('{}') Time=20220108-20:22:35.8607",
+            disk
+        )
+        .unwrap();
+    }
+}
+
+/// Waits until the monitor has read the given amount of lines
+fn wait_for_lines(monitor: &Monitor, lines: u64) -> MonitorStatus {
+    let started = Instant::now();
+    loop {
+        let status = monitor.status(TIMEOUT).unwrap();
+        if status.lines_read >= lines || started.elapsed() > TIMEOUT {
+            return status;
+        }
+    }
+}
+
+/// Waits until the actions sent so far were applied, as they are
+/// applied in order
+fn settle(monitor: &Monitor) {
+    monitor.status(TIMEOUT).unwrap();
+}
+
+/// The events received until none arrived for a while
+fn received(monitor: &mut Monitor) -> Vec<String> {
+    let mut disks = Vec::new();
+    while let Some(item) = monitor.next_timeout(Duration::from_millis(100)) {
+        disks.push(item.unwrap().bsd_name().unwrap());
+    }
+    disks
+}
+
+#[test]
+fn test_pause_buffers_until_resumed() {
+    let (mut monitor, mut stream) = connected();
+    monitor.pause(PausePolicy::Buffer(2));
+    settle(&monitor);
+    write_peeks(&mut stream, &["disk1", "disk2", "disk3"]);
+
+    let status = wait_for_lines(&monitor, 3);
+    assert_equal!(status.paused, true);
+    assert_equal!(status.buffered, 2);
+    assert_equal!(status.dropped, 1);
+    assert_equal!(monitor.try_next().is_none(), true);
+
+    monitor.flush();
+    assert_equal!(received(&mut monitor), vec!["disk2", "disk3"]);
+
+    write_peeks(&mut stream, &["disk4"]);
+    wait_for_lines(&monitor, 4);
+    monitor.resume();
+    settle(&monitor);
+    write_peeks(&mut stream, &["disk5"]);
+    assert_equal!(received(&mut monitor), vec!["disk4", "disk5"]);
+}
+
+#[test]
+fn test_pause_can_drop_events() {
+    let (mut monitor, mut stream) = connected();
+    monitor.pause(PausePolicy::Drop);
+    settle(&monitor);
+    write_peeks(&mut stream, &["disk1", "disk2"]);
+    assert_equal!(wait_for_lines(&monitor, 2).dropped, 2);
+
+    monitor.resume();
+    settle(&monitor);
+    write_peeks(&mut stream, &["disk3"]);
+    assert_equal!(received(&mut monitor), vec!["disk3"]);
+}
+
+#[test]
+fn test_set_filter_without_restart() {
+    let (mut monitor, mut stream) = connected();
+    monitor.set_filter(EventFilter::all().disks(vec!["disk2"]));
+    settle(&monitor);
+    write_peeks(&mut stream, &["disk1", "disk2s1", "disk3"]);
+    assert_equal!(received(&mut monitor), vec!["disk2s1"]);
+
+    monitor.set_filter(EventFilter::all().kinds(vec![EventKind::DiskPeek]));
+    settle(&monitor);
+    write_peeks(&mut stream, &["disk1"]);
+    assert_equal!(received(&mut monitor), vec!["disk1"]);
+
+    let status = monitor.status(TIMEOUT).unwrap();
+    assert_equal!(status.filtered, 2);
+    assert_equal!(status.forwarded, 2);
+}
+
+#[test]
+fn test_status() {
+    let (mut monitor, mut stream) = connected();
+    writeln!(stream, "garbage").unwrap();
+    write_peeks(&mut stream, &["disk1"]);
+    assert_equal!(monitor.next().unwrap().is_err(), true);
+    assert_equal!(monitor.next().unwrap().is_ok(), true);
+
+    let status = monitor.status(TIMEOUT).unwrap();
+    assert_equal!(status.pid, None);
+    assert_equal!(status.lines_read, 2);
+    assert_equal!(status.forwarded, 2);
+    assert_equal!(status.paused, false);
+    assert_equal!(status.last_error.unwrap().contains("garbage"), true);
+    assert_equal!(status.uptime > Duration::ZERO, true);
+
+    let command = MonitorBuilder::new()
+        .command("sleep")
+        .arg("10")
+        .build()
+        .unwrap();
+    assert_equal!(command.status(TIMEOUT).unwrap().pid, command.pid());
+}
+
+#[test]
+fn test_actions_compare_equal() {
+    let (first, _) = std::sync::mpsc::channel();
+    let (second, _) = std::sync::mpsc::channel();
+
+    assert_equal!(Action::Stop, Action::Stop);
+    assert_equal!(
+        Action::Pause(PausePolicy::Buffer(4)) == Action::Pause(PausePolicy::Drop),
+        false
+    );
+    assert_equal!(Action::Status(first), Action::Status(second));
+}
@@ -0,0 +1,63 @@
+use k9::assert_equal;
+use mac_disk_monitor::event::Event;
+use serde_json::Value;
+
+fn corpus() -> Vec<Value> {
+    include_str!("corpus/tricky-lines.jsonl")
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+fn expected(case: &Value, key: &str) -> Option<String> {
+    case[key].as_str().map(String::from)
+}
+
+#[test]
+fn test_tricky_lines() {
+    for case in corpus() {
+        let line = case["line"].as_str().unwrap();
+        let event = Event::parse(line).unwrap();
+
+        assert_equal!(
+            (line, event.name().to_string()),
+            (line, expected(&case, "name").unwrap())
+        );
+        assert_equal!(
+            (line, event.bsd_name()),
+            (line, expected(&case, "bsd_name"))
+        );
+        assert_equal!((line, event.path()), (line, expected(&case, "volume_path")));
+        assert_equal!((line, event.kind()), (line, expected(&case, "volume_kind")));
+        assert_equal!(
+            (line, event.volume_name()),
+            (line, expected(&case, "volume_name"))
+        );
+        assert_equal!((line, event.comment()), (line, expected(&case, "comment")));
+    }
+}
+
+#[test]
+fn test_tricky_lines_extractors_agree_with_parser() {
+    use mac_disk_monitor::event::{extract_volume_kind, extract_volume_name, extract_volume_path};
+
+    for case in corpus() {
+        let line = case["line"].as_str().unwrap();
+        let event = Event::parse(line).unwrap();
+        let null = |value: Option<String>| value.filter(|value| value != "<null>");
+
+        assert_equal!(
+            (line, null(extract_volume_path(line))),
+            (line, event.path())
+        );
+        assert_equal!(
+            (line, null(extract_volume_kind(line))),
+            (line, event.kind())
+        );
+        assert_equal!(
+            (line, null(extract_volume_name(line))),
+            (line, event.volume_name())
+        );
+    }
+}
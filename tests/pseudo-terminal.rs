@@ -0,0 +1,75 @@
+#![cfg(unix)]
+use k9::assert_equal;
+use mac_disk_monitor::{
+    stream_events_with_pty, Error, EventKind, Monitor, MonitorBuilder, WindowSize,
+};
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+
+fn fixture() -> MonitorBuilder {
+    MonitorBuilder::new().command("./tests/tty-buffering.sh")
+}
+
+fn next_kind(monitor: &mut Monitor) -> EventKind {
+    monitor.next().unwrap().unwrap().name()
+}
+
+#[test]
+fn test_pty_delivers_each_line_as_printed() {
+    let started = Instant::now();
+    let mut monitor = fixture().pty(WindowSize::default()).build().unwrap();
+
+    let event = monitor.next().unwrap().unwrap();
+    assert_equal!(started.elapsed() < Duration::from_millis(700), true);
+    assert_equal!(event.name(), EventKind::DiskAppeared);
+    assert_equal!(event.volume_name(), Some(String::from("Backups")));
+    assert_equal!(next_kind(&mut monitor), EventKind::DiskDisappeared);
+    assert_equal!(
+        matches!(monitor.next(), Some(Err(Error::ChildExited { .. }))),
+        true
+    );
+    assert_equal!(monitor.next().is_none(), true);
+}
+
+#[test]
+fn test_pipe_delivers_lines_at_exit() {
+    let started = Instant::now();
+    let mut monitor = fixture().build().unwrap();
+
+    assert_equal!(next_kind(&mut monitor), EventKind::DiskAppeared);
+    assert_equal!(started.elapsed() >= Duration::from_millis(1900), true);
+}
+
+#[test]
+fn test_pty_window_size() {
+    let mut monitor = MonitorBuilder::new()
+        .command("sh")
+        // stdin is not the terminal, only stdout is
+        .args(vec!["-c", "stty size <&1"])
+        .pty(WindowSize::new(30, 200))
+        .build()
+        .unwrap();
+    match monitor.next() {
+        Some(Err(Error::Parse { line, .. })) => {
+            assert_equal!(line, "30 200");
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_stream_events_with_pty() {
+    let (_action, receiver) = channel();
+    let (thread, events) = stream_events_with_pty(
+        "./tests/tty-buffering.sh",
+        vec![],
+        WindowSize::default(),
+        receiver,
+    );
+    assert_equal!(
+        events.recv().unwrap().unwrap().name(),
+        EventKind::DiskAppeared
+    );
+    drop(events);
+    assert_equal!(thread.join().unwrap().is_ok(), true);
+}
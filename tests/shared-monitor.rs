@@ -0,0 +1,203 @@
+use k9::assert_equal;
+use mac_disk_monitor::{Error, EventKind, MonitorBuilder, SharedMonitor, Subscription};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Prints the given lines, then keeps running like `diskutil activity`
+fn shared(lines: &[&str], then: &str) -> SharedMonitor {
+    let script = format!(
+        "printf '%s\\n' {}; {}",
+        lines
+            .iter()
+            .map(|line| format!("\"{}\"", line))
+            .collect::<Vec<_>>()
+            .join(" "),
+        then
+    );
+    SharedMonitor::new(
+        MonitorBuilder::new()
+            .command("sh")
+            .args(vec!["-c", &script]),
+    )
+}
+
+fn appeared(disk: &str) -> String {
+    format!(
+        "***DiskAppeared ('{}', DAVolumePath = '<null>') Time=20220108-20:22:05.1438",
+        disk
+    )
+}
+
+fn disappeared(disk: &str) -> String {
+    format!(
+        "***DiskDisappeared ('{}', DAVolumePath = '<null>') Time=20220108-20:22:07.1438",
+        disk
+    )
+}
+
+/// The kind and disk of the next events, until none arrived for a while
+fn received(subscription: &mut Subscription) -> Vec<String> {
+    let mut received = Vec::new();
+    while let Some(item) = subscription.next_timeout(Duration::from_millis(200)) {
+        let event = item.unwrap();
+        received.push(format!("{} {}", event.name(), event.bsd_name().unwrap()));
+    }
+    received
+}
+
+fn is_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .status()
+        .unwrap()
+        .success()
+}
+
+#[test]
+fn test_fans_events_out_to_every_subscriber() {
+    let monitor = shared(&[&appeared("disk4")], "exec sleep 30");
+    let mut first = monitor.subscribe().unwrap();
+    let mut second = monitor.subscribe().unwrap();
+    let pid = monitor.pid();
+
+    assert_equal!(pid.is_some(), true);
+    assert_equal!(monitor.subscribers(), 2);
+    assert_equal!(received(&mut first), vec!["DiskAppeared disk4"]);
+    assert_equal!(received(&mut second), vec!["DiskAppeared disk4"]);
+    assert_equal!(monitor.pid(), pid);
+}
+
+#[test]
+fn test_concurrent_subscriptions_start_a_single_command() {
+    let monitor = shared(&[&appeared("disk4")], "exec sleep 30");
+    let subscribers: Vec<_> = (0..8)
+        .map(|_| {
+            let monitor = monitor.clone();
+            thread::spawn(move || {
+                let subscription = monitor.subscribe().unwrap();
+                (monitor.pid(), subscription)
+            })
+        })
+        .collect();
+    let subscribed: Vec<_> = subscribers
+        .into_iter()
+        .map(|subscriber| subscriber.join().unwrap())
+        .collect();
+
+    assert_equal!(monitor.subscribers(), 8);
+    for (pid, _) in &subscribed {
+        assert_equal!(*pid, monitor.pid());
+    }
+}
+
+#[test]
+fn test_stops_the_command_with_the_last_subscriber() {
+    let monitor = shared(&[&appeared("disk4")], "exec sleep 30");
+    let first = monitor.subscribe().unwrap();
+    let second = monitor.subscribe().unwrap();
+    let pid = monitor.pid().unwrap();
+
+    drop(first);
+    assert_equal!(monitor.subscribers(), 1);
+    assert_equal!(is_alive(pid), true);
+
+    drop(second);
+    assert_equal!(monitor.pid(), None);
+    let dropped = Instant::now();
+    while is_alive(pid) && dropped.elapsed() < TIMEOUT {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_equal!(is_alive(pid), false);
+
+    let _again = monitor.subscribe().unwrap();
+    assert_equal!(monitor.pid().is_some_and(|again| again != pid), true);
+}
+
+#[test]
+fn test_late_subscribers_can_replay_the_inventory() {
+    let monitor = shared(
+        &[
+            &appeared("disk4"),
+            &appeared("disk5"),
+            &disappeared("disk4"),
+            &appeared("disk6s1"),
+        ],
+        "exec sleep 30",
+    );
+    let mut first = monitor.subscribe().unwrap();
+    assert_equal!(received(&mut first).len(), 4);
+
+    let mut late = monitor.subscribe_with_inventory().unwrap();
+    assert_equal!(
+        received(&mut late),
+        vec!["DiskAppeared disk5", "DiskAppeared disk6s1"]
+    );
+    let mut later = monitor.subscribe().unwrap();
+    assert_equal!(later.try_next().is_none(), true);
+    assert_equal!(monitor.inventory().len(), 2);
+}
+
+#[test]
+fn test_late_subscribers_see_changed_descriptions() {
+    let monitor = shared(
+        &[
+            "***DiskAppeared ('disk4s1', DAVolumePath = '<null>', DAVolumeKind = 'msdos', DAVolumeName = 'UNTITLED') Time=20220108-20:22:05.1438",
+            "***DiskDescriptionChanged ('disk4s1', DAVolumePath = 'file:///Volumes/UNTITLED/') Time=20220108-20:22:06.1438",
+        ],
+        "exec sleep 30",
+    );
+    let mut first = monitor.subscribe().unwrap();
+    assert_equal!(received(&mut first).len(), 2);
+
+    let mut late = monitor.subscribe_with_inventory().unwrap();
+    let event = late.next_timeout(TIMEOUT).unwrap().unwrap();
+    assert_equal!(event.name(), EventKind::DiskAppeared);
+    assert_equal!(
+        event.path(),
+        Some(String::from("file:///Volumes/UNTITLED/"))
+    );
+    assert_equal!(event.volume_name(), Some(String::from("UNTITLED")));
+    assert_equal!(late.try_next().is_none(), true);
+}
+
+#[test]
+fn test_timeouts_are_told_apart_from_the_end() {
+    let monitor = shared(&[&appeared("disk4")], "sleep 0.3");
+    let mut subscription = monitor.subscribe().unwrap();
+
+    assert_equal!(subscription.next_timeout(TIMEOUT).unwrap().is_ok(), true);
+    assert_equal!(subscription.try_next().is_none(), true);
+    assert_equal!(subscription.is_finished(), false);
+
+    // the exit of the command, then the end
+    assert_equal!(subscription.next_timeout(TIMEOUT).unwrap().is_err(), true);
+    assert_equal!(subscription.next_timeout(TIMEOUT).is_none(), true);
+    assert_equal!(subscription.is_finished(), true);
+}
+
+#[test]
+fn test_subscriptions_end_with_the_command() {
+    let monitor = shared(&[&appeared("disk4")], "exit 3");
+    let mut subscription = monitor.subscribe().unwrap();
+
+    assert_equal!(
+        subscription.next().unwrap().unwrap().name(),
+        EventKind::DiskAppeared
+    );
+    match subscription.next() {
+        Some(Err(Error::ChildExited { status, .. })) => {
+            assert_equal!(status.code(), Some(3));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_equal!(subscription.next().is_none(), true);
+    assert_equal!(subscription.is_finished(), true);
+    assert_equal!(monitor.pid(), None);
+
+    // the next subscription starts the command again
+    let mut again = monitor.subscribe().unwrap();
+    assert_equal!(again.next().unwrap().is_ok(), true);
+}
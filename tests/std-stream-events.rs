@@ -14,9 +14,8 @@ fn test_disk_activity() {
 
     let event = receiver
         .recv_timeout(Duration::from_secs(5))
-        .unwrap_or(None);
-    assert_ne!(event, None);
-    let event = event.unwrap();
+        .unwrap()
+        .unwrap();
     assert_equal!(event.name(), "DiskAppeared");
     assert_equal!(
         event.path().unwrap_or(String::new()),
@@ -53,3 +52,139 @@ fn test_stop_thread() {
 
     thread.join().unwrap().unwrap();
 }
+
+fn is_running(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap()
+        .success()
+}
+
+#[test]
+fn test_event_stream_iterates_events() {
+    let mut stream = EventStream::with_command("./tests/dummy-disk-activity.sh", vec![]).unwrap();
+
+    let event = stream.next().unwrap().unwrap();
+    assert_equal!(event.name(), "DiskAppeared");
+    assert_equal!(event.bsd_name(), Some(String::from("disk4")));
+
+    let event = stream.next().unwrap().unwrap();
+    assert_equal!(event.bsd_name(), Some(String::from("disk3s2")));
+
+    stream.stop().unwrap();
+}
+
+#[test]
+fn test_event_stream_timeouts() {
+    let mut stream = EventStream::with_command("sleep", vec!["10"]).unwrap();
+
+    assert_equal!(stream.try_next().is_none(), true);
+    assert_equal!(
+        stream.next_timeout(Duration::from_millis(500)).is_none(),
+        true
+    );
+    assert_equal!(stream.is_finished(), false);
+}
+
+#[test]
+fn test_event_stream_finishes_when_command_exits() {
+    let mut stream = EventStream::with_command("true", vec![]).unwrap();
+
+    assert_equal!(
+        matches!(stream.next(), Some(Err(Error::ChildExited { .. }))),
+        true
+    );
+    assert_equal!(stream.next().is_none(), true);
+    assert_equal!(stream.is_finished(), true);
+    assert_equal!(stream.next().is_none(), true);
+}
+
+#[test]
+fn test_event_stream_kills_and_reaps_on_drop() {
+    let stream = EventStream::with_command("sleep", vec!["10"]).unwrap();
+    let pid = stream.pid().unwrap();
+    assert_equal!(is_running(pid), true);
+
+    drop(stream);
+    assert_equal!(is_running(pid), false);
+}
+
+#[test]
+fn test_event_stream_spawn_error() {
+    let error = EventStream::with_command("./tests/does-not-exist", vec![]).err();
+
+    assert_equal!(matches!(error, Some(Error::Spawn { .. })), true);
+}
+
+#[test]
+fn test_child_exit_is_reported_with_stderr_tail() {
+    let (_action_sender, action_receiver) = channel();
+    let (thread, receiver) = stream_events_with_command(
+        "sh",
+        vec![
+            "-c",
+            "echo \"***DiskPeek ('disk3') Time=20220108-20:22:05.1438\"; echo 'diskutil: boom' >&2; exit 3",
+        ],
+        action_receiver,
+    );
+
+    let event = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_equal!(event.unwrap().bsd_name(), Some(String::from("disk3")));
+
+    let error = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    match error {
+        Err(Error::ChildExited {
+            status,
+            stderr_tail,
+        }) => {
+            assert_equal!(status.code(), Some(3));
+            assert_equal!(stderr_tail, vec![String::from("diskutil: boom")]);
+        }
+        other => panic!("expected ChildExited, got {:?}", other),
+    }
+    assert_equal!(
+        matches!(thread.join().unwrap(), Err(Error::ChildExited { .. })),
+        true
+    );
+}
+
+#[test]
+fn test_parse_errors_do_not_stop_the_thread() {
+    let (_action_sender, action_receiver) = channel();
+    let (_thread, receiver) = stream_events_with_command(
+        "sh",
+        vec![
+            "-c",
+            "echo 'garbage'; echo; echo \"***DiskPeek ('disk3') Time=20220108-20:22:05.1438\"; sleep 2",
+        ],
+        action_receiver,
+    );
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        Err(Error::Parse { line, source }) => {
+            assert_equal!(line, "garbage");
+            assert_equal!(source.offset(), 0);
+        }
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+    let event = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_equal!(event.unwrap().bsd_name(), Some(String::from("disk3")));
+}
+
+#[test]
+fn test_spawn_error_reaches_the_channel() {
+    let (_action_sender, action_receiver) = channel();
+    let (thread, receiver) =
+        stream_events_with_command("./tests/does-not-exist", vec![], action_receiver);
+
+    assert_equal!(
+        matches!(receiver.recv().unwrap(), Err(Error::Spawn { .. })),
+        true
+    );
+    assert_equal!(
+        matches!(thread.join().unwrap(), Err(Error::Spawn { .. })),
+        true
+    );
+}
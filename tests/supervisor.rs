@@ -0,0 +1,205 @@
+use k9::assert_equal;
+use mac_disk_monitor::{
+    stream_events_supervised_with_command, supervise, Action, Error, MonitorBuilder, RestartPolicy,
+    SupervisorEvent,
+};
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+
+const FLAKY: &str =
+    "echo \"***DiskAppeared ('disk9', DAVolumePath = '<null>') Time=20220108-20:22:05.1454\"; exit 1";
+
+#[test]
+fn test_restarts_until_the_budget_is_exhausted() {
+    let policy = RestartPolicy::new()
+        .initial_backoff(Duration::from_millis(10))
+        .jitter(0.0)
+        .max_restarts(2);
+    let (_action, receiver) = channel();
+    let (thread, events) =
+        stream_events_supervised_with_command("sh", vec!["-c", FLAKY], policy, receiver);
+
+    let names: Vec<String> = events
+        .iter()
+        .map(|item| match item {
+            SupervisorEvent::Monitor(Ok(event)) => event.name().to_string(),
+            SupervisorEvent::Monitor(Err(Error::ChildExited { status, .. })) => {
+                format!("exited {}", status.code().unwrap())
+            }
+            SupervisorEvent::Monitor(Err(e)) => e.to_string(),
+            SupervisorEvent::Started { .. } => String::from("started"),
+            SupervisorEvent::Restarted { restart, .. } => format!("restart {}", restart),
+            SupervisorEvent::GaveUp { restarts, .. } => format!("gave up after {}", restarts),
+        })
+        .collect();
+    assert_equal!(
+        names,
+        vec![
+            "started",
+            "DiskAppeared",
+            "exited 1",
+            "restart 1",
+            "DiskAppeared",
+            "exited 1",
+            "restart 2",
+            "DiskAppeared",
+            "exited 1",
+            "gave up after 2",
+        ]
+    );
+    assert_equal!(
+        matches!(thread.join().unwrap(), Err(Error::ChildExited { .. })),
+        true
+    );
+}
+
+#[test]
+fn test_spawn_failures_count_against_the_budget() {
+    let policy = RestartPolicy::new()
+        .initial_backoff(Duration::from_millis(1))
+        .max_restarts(1);
+    let (_action, receiver) = channel();
+    let (thread, events) = supervise(
+        MonitorBuilder::new().command("./tests/does-not-exist"),
+        policy,
+        receiver,
+    );
+
+    let items: Vec<_> = events.iter().collect();
+    assert_equal!(items.len(), 3);
+    assert_equal!(
+        matches!(items[0], SupervisorEvent::Monitor(Err(Error::Spawn { .. }))),
+        true
+    );
+    assert_equal!(
+        matches!(items[1], SupervisorEvent::Monitor(Err(Error::Spawn { .. }))),
+        true
+    );
+    assert_equal!(
+        matches!(items[2], SupervisorEvent::GaveUp { restarts: 1, .. }),
+        true
+    );
+    assert_equal!(
+        matches!(thread.join().unwrap(), Err(Error::Spawn { .. })),
+        true
+    );
+}
+
+#[test]
+fn test_first_spawn_after_failures_is_a_start() {
+    let dir = std::env::temp_dir().join(format!("supervisor-start-{}", std::process::id()));
+    let _ = std::fs::remove_dir(&dir);
+    let policy = RestartPolicy::new()
+        .initial_backoff(Duration::from_millis(100))
+        .jitter(0.0)
+        .max_restarts(1);
+    let (_action, receiver) = channel();
+    let (thread, events) = supervise(
+        MonitorBuilder::new()
+            .command("sh")
+            .args(vec!["-c", FLAKY])
+            .current_dir(&dir),
+        policy,
+        receiver,
+    );
+
+    assert_equal!(
+        matches!(
+            events.recv().unwrap(),
+            SupervisorEvent::Monitor(Err(Error::Spawn { .. }))
+        ),
+        true
+    );
+    // the next attempt, after the backoff, finds its directory
+    std::fs::create_dir(&dir).unwrap();
+    assert_equal!(
+        matches!(events.recv().unwrap(), SupervisorEvent::Started { .. }),
+        true
+    );
+    assert_equal!(
+        matches!(events.recv().unwrap(), SupervisorEvent::Monitor(Ok(_))),
+        true
+    );
+    let _ = thread.join();
+    let _ = std::fs::remove_dir(&dir);
+}
+
+#[test]
+fn test_stop_action_interrupts_the_backoff() {
+    let policy = RestartPolicy::new().initial_backoff(Duration::from_secs(30));
+    let (action, receiver) = channel();
+    let (thread, events) =
+        stream_events_supervised_with_command("sh", vec!["-c", FLAKY], policy, receiver);
+
+    assert_equal!(
+        matches!(
+            events.recv().unwrap(),
+            SupervisorEvent::Started { pid: Some(_), .. }
+        ),
+        true
+    );
+    assert_equal!(
+        matches!(events.recv().unwrap(), SupervisorEvent::Monitor(Ok(_))),
+        true
+    );
+    assert_equal!(
+        matches!(events.recv().unwrap(), SupervisorEvent::Monitor(Err(_))),
+        true
+    );
+
+    let stopping = Instant::now();
+    action.send(Action::Stop).unwrap();
+    assert_equal!(thread.join().unwrap().is_ok(), true);
+    assert_equal!(stopping.elapsed() < Duration::from_secs(5), true);
+}
+
+#[test]
+fn test_stop_action_stops_a_running_command() {
+    let (action, receiver) = channel();
+    let (thread, events) =
+        stream_events_supervised_with_command("sleep", vec!["10"], RestartPolicy::new(), receiver);
+
+    assert_equal!(
+        matches!(events.recv().unwrap(), SupervisorEvent::Started { .. }),
+        true
+    );
+    action.send(Action::Stop).unwrap();
+    assert_equal!(thread.join().unwrap().is_ok(), true);
+}
+
+#[test]
+fn test_channel_capacity_holds_the_supervisor_back() {
+    let policy = RestartPolicy::new().max_restarts(0);
+    let builder = MonitorBuilder::new()
+        .command("sh")
+        .args(vec!["-c", FLAKY])
+        .channel_capacity(1);
+    let (_action, receiver) = channel();
+    let (thread, events) = supervise(builder, policy, receiver);
+
+    std::thread::sleep(Duration::from_millis(500));
+    assert_equal!(thread.is_finished(), false);
+    assert_equal!(events.iter().count(), 4);
+    assert_equal!(thread.join().unwrap().is_err(), true);
+}
+
+#[test]
+fn test_actions_reach_the_running_command() {
+    let (action, receiver) = channel();
+    let (thread, events) =
+        stream_events_supervised_with_command("sleep", vec!["10"], RestartPolicy::new(), receiver);
+    let pid = match events.recv().unwrap() {
+        SupervisorEvent::Started { pid, .. } => pid,
+        other => panic!("unexpected {:?}", other),
+    };
+
+    let (status, statuses) = channel();
+    let asked = Instant::now();
+    action.send(Action::Status(status)).unwrap();
+    let status = statuses.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_equal!(status.pid, pid);
+    assert_equal!(asked.elapsed() < Duration::from_millis(50), true);
+
+    action.send(Action::Stop).unwrap();
+    assert_equal!(thread.join().unwrap().is_ok(), true);
+}
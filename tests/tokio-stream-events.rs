@@ -0,0 +1,176 @@
+#![cfg(feature = "async")]
+use futures_util::StreamExt;
+use k9::assert_equal;
+use mac_disk_monitor::tokio::*;
+use mac_disk_monitor::{Action, ParseOptions};
+use std::time::Duration;
+use tokio::sync::mpsc::channel;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+fn is_running(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap()
+        .success()
+}
+
+#[tokio::test]
+async fn test_disk_activity_async() {
+    let (_action, receiver) = channel(1);
+    let mut events =
+        stream_events_async_with_command("./tests/dummy-disk-activity.sh", vec![], receiver)
+            .unwrap();
+
+    let event = timeout(Duration::from_secs(5), events.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_equal!(event.name(), "DiskAppeared");
+    assert_equal!(
+        event.path().unwrap_or_default(),
+        "file:///Volumes/my%20backups/"
+    );
+}
+
+#[tokio::test]
+async fn test_stop_action_ends_stream() {
+    let (action, receiver) = channel(1);
+    let mut events = stream_events_async_with_command("sleep", vec!["10"], receiver).unwrap();
+
+    assert_equal!(
+        timeout(Duration::from_millis(300), events.next())
+            .await
+            .is_err(),
+        true
+    );
+    action.send(Action::Noop).await.unwrap();
+    action.send(Action::Stop).await.unwrap();
+    let end = timeout(Duration::from_secs(2), events.next())
+        .await
+        .unwrap();
+    assert_equal!(end.is_none(), true);
+}
+
+#[tokio::test]
+async fn test_cancellation_token_ends_stream() {
+    let (_action, receiver) = channel(1);
+    let cancel = CancellationToken::new();
+    let mut events = stream_events_async_with_options(
+        "sleep",
+        vec!["10"],
+        ParseOptions::default(),
+        receiver,
+        cancel.clone(),
+    )
+    .unwrap();
+    let pid = events.pid().unwrap();
+    assert_equal!(is_running(pid), true);
+
+    cancel.cancel();
+    let end = timeout(Duration::from_secs(2), events.next())
+        .await
+        .unwrap();
+    assert_equal!(end.is_none(), true);
+    assert_equal!(is_running(pid), false);
+}
+
+#[tokio::test]
+async fn test_drop_kills_and_reaps() {
+    let (_action, receiver) = channel(1);
+    let events = stream_events_async_with_command("sleep", vec!["10"], receiver).unwrap();
+    let pid = events.pid().unwrap();
+    assert_equal!(is_running(pid), true);
+
+    drop(events);
+    let mut running = true;
+    for _ in 0..20 {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        running = is_running(pid);
+        if !running {
+            break;
+        }
+    }
+    assert_equal!(running, false);
+}
+
+#[tokio::test]
+async fn test_spawn_error_async() {
+    let (_action, receiver) = channel(1);
+    let error = stream_events_async_with_command("./tests/does-not-exist", vec![], receiver).err();
+
+    assert_equal!(error.is_some(), true);
+}
+
+#[tokio::test]
+async fn test_spawn_error_is_reported_async() {
+    let (_action, receiver) = channel(1);
+    let error = stream_events_async_with_command("./tests/does-not-exist", vec![], receiver)
+        .err()
+        .unwrap();
+
+    assert_equal!(matches!(error, mac_disk_monitor::Error::Spawn { .. }), true);
+}
+
+#[tokio::test]
+async fn test_child_exit_is_reported_async() {
+    let (_action, receiver) = channel(1);
+    let mut events = stream_events_async_with_command(
+        "sh",
+        vec!["-c", "echo garbage; echo 'diskutil: boom' >&2; exit 3"],
+        receiver,
+    )
+    .unwrap();
+
+    let parse_error = timeout(Duration::from_secs(2), events.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap_err();
+    assert_equal!(
+        matches!(parse_error, mac_disk_monitor::Error::Parse { ref line, .. } if line == "garbage"),
+        true
+    );
+    match timeout(Duration::from_secs(2), events.next())
+        .await
+        .unwrap()
+    {
+        Some(Err(mac_disk_monitor::Error::ChildExited {
+            status,
+            stderr_tail,
+        })) => {
+            assert_equal!(status.code(), Some(3));
+            assert_equal!(stderr_tail, vec![String::from("diskutil: boom")]);
+        }
+        other => panic!("expected ChildExited, got {:?}", other),
+    }
+    assert_equal!(events.next().await.is_none(), true);
+}
+
+#[tokio::test]
+async fn test_last_line_without_newline_async() {
+    let (action, receiver) = channel(1);
+    let mut events = stream_events_async_with_command(
+        "sh",
+        vec![
+            "-c",
+            "printf \"***DiskPeek ('disk3s1') Time=20220108-20:22:35.8607\"; sleep 0.3",
+        ],
+        receiver,
+    )
+    .unwrap();
+
+    // interrupts the read of the partial line before the end of stdout
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    action.send(Action::Noop).await.unwrap();
+
+    let event = timeout(Duration::from_secs(2), events.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_equal!(event.bsd_name(), Some(String::from("disk3s1")));
+}
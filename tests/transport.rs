@@ -0,0 +1,132 @@
+#![cfg(unix)]
+use k9::assert_equal;
+use mac_disk_monitor::{
+    supervise, Error, Event, MonitorBuilder, RestartPolicy, SupervisorEvent, Transport,
+};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+const APPEARED: &str = "***DiskAppeared ('disk4', DAVolumePath = '<null>', DAVolumeKind = 'hfs', DAVolumeName = 'It''s mine') Time=20220108-20:22:05.1438";
+const DISAPPEARED: &str =
+    "***DiskDisappeared ('disk4', DAVolumePath = '<null>') Time=20220108-20:22:07.1438";
+
+fn fake_ssh(host: &str) -> Transport {
+    Transport::ssh(host).program("./tests/fake-ssh.sh")
+}
+
+/// Prints both events, with quotes and parentheses the remote shell
+/// must not interpret
+fn printing_events() -> MonitorBuilder {
+    MonitorBuilder::new()
+        .command("printf")
+        .args(vec!["%s\\n", APPEARED, DISAPPEARED])
+}
+
+/// The name and host of each event, or what ended a run
+fn outline(items: impl Iterator<Item = Result<Event, Error>>) -> Vec<String> {
+    items
+        .map(|item| match item {
+            Ok(event) => format!("{} on {}", event.name(), event.host().unwrap_or("?")),
+            Err(Error::ConnectionLost { host, .. }) => format!("lost {}", host),
+            Err(Error::ChildExited { status, .. }) => format!("exited {}", status.code().unwrap()),
+            Err(e) => e.to_string(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_ssh_runs_the_command_remotely_and_labels_events() {
+    let monitor = printing_events()
+        .transport(fake_ssh("build-1"))
+        .build()
+        .unwrap();
+    let events: Vec<_> = monitor.collect();
+    assert_equal!(
+        events[0].as_ref().unwrap().volume_name(),
+        Some(String::from("It''s mine"))
+    );
+    assert_equal!(
+        outline(events.into_iter()),
+        vec![
+            "DiskAppeared on build-1",
+            "DiskDisappeared on build-1",
+            "exited 0"
+        ]
+    );
+}
+
+#[test]
+fn test_unreachable_host() {
+    let mut monitor = printing_events()
+        .transport(fake_ssh("unreachable"))
+        .build()
+        .unwrap();
+    match monitor.next() {
+        Some(Err(Error::ConnectionLost {
+            host, stderr_tail, ..
+        })) => {
+            assert_equal!(host, "unreachable");
+            assert_equal!(stderr_tail[0].contains("Connection refused"), true);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_reconnects_after_losing_the_connection() {
+    let marker = std::env::temp_dir().join(format!("fake-ssh-drop-{}", std::process::id()));
+    let _ = std::fs::remove_file(&marker);
+    let builder = printing_events()
+        .transport(fake_ssh("build-2"))
+        .env("FAKE_SSH_DROP_ONCE", &marker);
+    let policy = RestartPolicy::new()
+        .initial_backoff(Duration::from_millis(10))
+        .jitter(0.0)
+        .max_restarts(1);
+    let (_action, receiver) = channel();
+    let (thread, events) = supervise(builder, policy, receiver);
+
+    let outline: Vec<String> = events
+        .iter()
+        .map(|item| match item {
+            SupervisorEvent::Monitor(item) => outline(std::iter::once(item)).remove(0),
+            SupervisorEvent::Started { host, .. } => format!("started on {}", host.unwrap()),
+            SupervisorEvent::Restarted { host, .. } => format!("restarted on {}", host.unwrap()),
+            SupervisorEvent::GaveUp { host, .. } => format!("gave up on {}", host.unwrap()),
+        })
+        .collect();
+    assert_equal!(
+        outline,
+        vec![
+            "started on build-2",
+            "DiskAppeared on build-2",
+            "lost build-2",
+            "restarted on build-2",
+            "DiskAppeared on build-2",
+            "DiskDisappeared on build-2",
+            "exited 0",
+            "gave up on build-2",
+        ]
+    );
+    assert_equal!(thread.join().unwrap().is_err(), true);
+    let _ = std::fs::remove_file(&marker);
+}
+
+#[test]
+fn test_prefix_with_a_host_label() {
+    let monitor = printing_events()
+        .transport(Transport::prefix("env", vec!["LC_ALL=C"]).label("container"))
+        .build()
+        .unwrap();
+    assert_equal!(
+        outline(monitor),
+        vec![
+            "DiskAppeared on container",
+            "DiskDisappeared on container",
+            "exited 0",
+        ]
+    );
+
+    let monitor = printing_events().host_label("local").build().unwrap();
+    assert_equal!(outline(monitor)[0], "DiskAppeared on local");
+}
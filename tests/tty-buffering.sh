@@ -0,0 +1,19 @@
+#!/usr/bin/env bash
+# Prints events the way a program buffered by the C library does: each
+# line right away on a terminal, in color, but all of them at exit when
+# stdout is a pipe.
+
+LINES=(
+    "***DiskAppeared ('disk4', DAVolumePath = '<null>', DAVolumeKind = 'hfs', DAVolumeName = 'Backups') Time=20220108-20:22:05.1438"
+    "***DiskDisappeared ('disk4', DAVolumePath = '<null>', DAVolumeKind = 'hfs', DAVolumeName = 'Backups') Time=20220108-20:22:07.1438"
+)
+
+if [ -t 1 ]; then
+    for line in "${LINES[@]}"; do
+        printf '\033]0;diskutil\007\033[1;32m%s\033[0m\n' "$line"
+        sleep 1
+    done
+else
+    sleep 2
+    printf '%s\n' "${LINES[@]}"
+fi